@@ -0,0 +1,268 @@
+//! Exact cover as satisfiability, for checking instances against
+//! external SAT solvers.
+//!
+//! Variable `r + 1` says that row `r` is in the cover.  Each primary
+//! column gets a clause saying that at least one of its rows is
+//! chosen, and every column gets an at-most-one constraint over its
+//! rows, encoded in one of the ways of `AtMostOne` (some of which
+//! need auxiliary variables, numbered after the rows).  A row with
+//! no ones in primary columns is ruled out, as the exact cover
+//! solvers never choose it.
+
+use std::io::{self, Write};
+
+use exact_cover::{BitMatrix, RowLabelled, Accumulate};
+
+/// How to say that at most one of a list of variables is true.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum AtMostOne {
+    /// A clause `-x -y` for each pair: no new variables, but
+    /// quadratically many clauses.
+    Pairwise,
+    /// Sinz's sequential counter: n-1 new variables, 3n-4 clauses.
+    Sequential,
+    /// Klieber and Kwon's commander encoding, with groups of three:
+    /// each group gets a commander variable, true exactly when one of
+    /// the group is, and the commanders are constrained in turn.
+    Commander,
+}
+
+/// A formula in conjunctive normal form, with literals as in DIMACS:
+/// `v` or `-v` for variable `v`, counting from 1.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Cnf {
+    pub num_vars: usize,
+    pub clauses: Vec<Vec<isize>>,
+}
+
+impl Cnf {
+    fn new_var(&mut self) -> isize {
+        self.num_vars += 1;
+        self.num_vars as isize
+    }
+
+    fn at_most_one(&mut self, vars: &[isize], how: AtMostOne) {
+        match how {
+            AtMostOne::Pairwise => self.pairwise(vars),
+            AtMostOne::Sequential => self.sequential(vars),
+            AtMostOne::Commander => self.commander(vars),
+        }
+    }
+
+    fn pairwise(&mut self, vars: &[isize]) {
+        for i in 0..vars.len() {
+            for j in i+1..vars.len() {
+                self.clauses.push(vec![-vars[i], -vars[j]]);
+            }
+        }
+    }
+
+    fn sequential(&mut self, vars: &[isize]) {
+        let n = vars.len();
+        if n <= 1 { return; }
+        // s[i] is true if any of vars[0..i+1] is.
+        let s: Vec<isize> = (0..n-1).map(|_| self.new_var()).collect();
+        self.clauses.push(vec![-vars[0], s[0]]);
+        for i in 1..n-1 {
+            self.clauses.push(vec![-vars[i], s[i]]);
+            self.clauses.push(vec![-s[i-1], s[i]]);
+            self.clauses.push(vec![-vars[i], -s[i-1]]);
+        }
+        self.clauses.push(vec![-vars[n-1], -s[n-2]]);
+    }
+
+    fn commander(&mut self, vars: &[isize]) {
+        const GROUP: usize = 3;
+        if vars.len() <= GROUP + 1 {
+            return self.pairwise(vars);
+        }
+        let mut commanders = vec![];
+        for group in vars.chunks(GROUP) {
+            let c = self.new_var();
+            self.pairwise(group);
+            let mut some = vec![-c];
+            for &x in group.iter() {
+                self.clauses.push(vec![-x, c]);
+                some.push(x);
+            }
+            self.clauses.push(some);
+            commanders.push(c);
+        }
+        self.commander(&commanders);
+    }
+
+    pub fn write_dimacs<W:Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "p cnf {} {}", self.num_vars, self.clauses.len())?;
+        for clause in self.clauses.iter() {
+            for lit in clause.iter() {
+                write!(w, "{} ", lit)?;
+            }
+            writeln!(w, "0")?;
+        }
+        Ok(())
+    }
+}
+
+/// Encodes the exact cover problem for `m`.
+pub fn encode<M:BitMatrix>(m: &M, how: AtMostOne) -> Cnf {
+    encode_with_secondary(m, |_| false, how)
+}
+
+/// Like `encode`, but the columns `c` with `is_secondary(c)` need
+/// only be covered at most once.
+pub fn encode_with_secondary<M, P>(m: &M, is_secondary: P, how: AtMostOne) -> Cnf
+    where M: BitMatrix, P: Fn(usize) -> bool
+{
+    let mut cnf = Cnf { num_vars: m.num_rows(), clauses: vec![] };
+    for r in 0..m.num_rows() {
+        let cols: Vec<usize> = m.cols_on(r, &Accumulate);
+        if cols.iter().all(|&c| is_secondary(c)) { cnf.clauses.push(vec![-(r as isize + 1)]); }
+    }
+    for c in 0..m.num_cols() {
+        let rows: Vec<usize> = m.rows_on(c, &Accumulate);
+        let vars: Vec<isize> = rows.iter().map(|&r| r as isize + 1).collect();
+        if !is_secondary(c) { cnf.clauses.push(vars.clone()); }
+        cnf.at_most_one(&vars, how);
+    }
+    cnf
+}
+
+/// The labels of the rows chosen by a satisfying assignment, given
+/// (as a SAT solver's `v` lines give it) by the literals that are
+/// true; literals of auxiliary variables are ignored.
+pub fn decode<L:Clone, M:BitMatrix+RowLabelled<L>>(m: &M, model: &[isize]) -> Vec<L> {
+    model.iter()
+        .filter(|&&lit| lit > 0 && (lit as usize) <= m.num_rows())
+        .map(|&lit| m.row_label(lit as usize - 1).clone())
+        .collect()
+}
+
+/// A plain DPLL procedure (unit propagation and branching, nothing
+/// cleverer), enough to check small encodings without leaving Rust.
+pub mod dpll {
+    use super::Cnf;
+
+    type Assignment = Vec<Option<bool>>; // indexed by variable
+
+    fn value(a: &Assignment, lit: isize) -> Option<bool> {
+        a[lit.unsigned_abs()].map(|v| v == (lit > 0))
+    }
+
+    /// Assigns the literals forced by unit clauses until none is
+    /// left; false if some clause is falsified.
+    fn propagate(cnf: &Cnf, a: &mut Assignment) -> bool {
+        loop {
+            let mut changed = false;
+            for clause in cnf.clauses.iter() {
+                let mut open = None;
+                let mut num_open = 0;
+                let mut sat = false;
+                for &lit in clause.iter() {
+                    match value(a, lit) {
+                        Some(true) => { sat = true; break; }
+                        Some(false) => {}
+                        None => { num_open += 1; open = Some(lit); }
+                    }
+                }
+                if sat { continue; }
+                match (num_open, open) {
+                    (0, _) => return false,
+                    (1, Some(lit)) => { a[lit.unsigned_abs()] = Some(lit > 0); changed = true; }
+                    _ => {}
+                }
+            }
+            if !changed { return true; }
+        }
+    }
+
+    fn search(cnf: &Cnf, a: &mut Assignment) -> bool {
+        if !propagate(cnf, a) { return false; }
+        match (1..a.len()).find(|&v| a[v].is_none()) {
+            None => true,
+            Some(v) => [true, false].iter().any(|&b| {
+                let mut a2 = a.clone();
+                a2[v] = Some(b);
+                if search(cnf, &mut a2) { *a = a2; true } else { false }
+            }),
+        }
+    }
+
+    /// A satisfying assignment, as the list of true literals (and
+    /// negations of the false ones), if there is one.
+    pub fn solve(cnf: &Cnf) -> Option<Vec<isize>> {
+        let mut a = vec![None; cnf.num_vars + 1];
+        if !search(cnf, &mut a) { return None; }
+        Some((1..a.len()).map(|v| if a[v] == Some(false) { -(v as isize) } else { v as isize })
+             .collect())
+    }
+
+    /// The number of assignments to variables `1..k+1` that extend
+    /// to satisfying assignments; with `k` the number of rows, this
+    /// counts exact covers, whatever auxiliary variables there are.
+    pub fn count_projected(cnf: &Cnf, k: usize) -> u64 {
+        fn count(cnf: &Cnf, a: &mut Assignment, k: usize) -> u64 {
+            if !propagate(cnf, a) { return 0; }
+            match (1..k+1).find(|&v| a[v].is_none()) {
+                None => if search(cnf, &mut a.clone()) { 1 } else { 0 },
+                Some(v) => [true, false].iter().map(|&b| {
+                    let mut a2 = a.clone();
+                    a2[v] = Some(b);
+                    count(cnf, &mut a2, k)
+                }).sum(),
+            }
+        }
+        count(cnf, &mut vec![None; cnf.num_vars + 1], k)
+    }
+}
+
+#[cfg(test)]
+fn dlx_count(inst: &::exact_cover::dlx1::Instance) -> u64 {
+    use dancing_links::Dlx;
+    let mut m = inst.dlx();
+    let stats = Dlx::new(&mut m).count();
+    stats.solutions
+}
+
+#[test]
+fn cnf_counts_match_dlx() {
+    use exact_cover::dlx1::Instance;
+    use puzzles::{langford, queens};
+
+    let instances: Vec<Instance> = vec![
+        queens::Queens::new(5).instance(),
+        queens::Queens::new(6).instance(),
+        langford::Langford::new(3).instance(),
+        langford::Langford::new(4).instance(),
+        Instance::read_from("a b c d | x\na b\nc d\na c x\nb d x\na\nb c d\nx\n".as_bytes()).unwrap(),
+    ];
+    for inst in instances.iter() {
+        let want = dlx_count(inst);
+        for &how in [AtMostOne::Pairwise, AtMostOne::Sequential, AtMostOne::Commander].iter() {
+            let m = inst.matrix();
+            let cnf = encode_with_secondary(m, |c| inst.is_secondary(c), how);
+            assert_eq!(dpll::count_projected(&cnf, m.num_rows()), want, "{:?} on {}", how, m);
+        }
+    }
+}
+
+#[test]
+fn cnf_decode() {
+    use exact_cover::simple_exact_cover_instance_1;
+
+    let m = simple_exact_cover_instance_1();
+    for &how in [AtMostOne::Pairwise, AtMostOne::Sequential, AtMostOne::Commander].iter() {
+        let cnf = encode(&m, how);
+        let mut soln = decode(&m, &dpll::solve(&cnf).unwrap());
+        soln.sort();
+        assert_eq!(soln, ["1", "4", "5"]);
+    }
+    let unsat = encode(&::exact_cover::simple_exact_cover_instance_2(), AtMostOne::Sequential);
+    assert_eq!(dpll::solve(&unsat), None);
+
+    let mut out = vec![];
+    encode(&m, AtMostOne::Pairwise).write_dimacs(&mut out).unwrap();
+    let text = String::from_utf8(out).unwrap();
+    // Columns a..g have 2, 2, 2, 3, 2, 2, 3 rows: 7 at-least-one
+    // clauses and 1+1+1+3+1+1+3 pairs.
+    assert!(text.starts_with("p cnf 6 18\n2 4 0\n-2 -4 0\n"));
+}
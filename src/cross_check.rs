@@ -0,0 +1,167 @@
+//! Checks that the exact cover solvers agree with one another.
+//!
+//! Each trial builds a small random `Matrix` from a seed and solves
+//! it with Algorithm X (both the cloning and the in-place drivers),
+//! with dancing links, and by trying every subset of the rows.  The
+//! solution sets must match up to the order of rows within a
+//! solution and of the solutions themselves.  When they do not, the
+//! instance is shrunk, a row or column at a time, to one that still
+//! shows the disagreement but has nothing left to remove.
+
+use exact_cover::{self, x, pre, BitMatrix, Matrix, RowLabelled};
+use dancing_links::{dlx_matrix, Dlx};
+use generators::n_tuples::Generator;
+
+type Instance = Matrix<String, usize>;
+type Solns = Vec<Vec<String>>;
+type Solver = fn(&Instance) -> Solns;
+
+/// Knuth's MMIX linear congruential generator; good enough to pick
+/// test instances, and reproducible from its seed.
+struct Lcg(u64);
+
+impl Lcg {
+    fn below(&mut self, n: u64) -> u64 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.0 >> 33) % n
+    }
+}
+
+/// A random instance with 1 to 6 columns and up to 8 rows.
+fn random_instance(seed: u64) -> Instance {
+    let mut rng = Lcg(seed);
+    let num_cols = 1 + rng.below(6) as usize;
+    let num_rows = rng.below(9) as usize;
+    let density = 1 + rng.below(3); // in thirds
+    let cols = (0..num_cols).map(|c| format!("c{}", c)).collect();
+    let rows = (0..num_rows).map(|r| format!("r{}", r)).collect();
+    let elems = (0..num_rows * num_cols)
+        .map(|_| if rng.below(3) < density { 1 } else { 0 })
+        .collect();
+    Matrix::new(cols, rows, elems)
+}
+
+fn normalize(solns: Solns) -> Solns {
+    let mut solns: Solns = solns.into_iter().map(|mut s| { s.sort(); s }).collect();
+    solns.sort();
+    solns
+}
+
+fn solve_x(m: &Instance) -> Solns {
+    let partial: Vec<String> = vec![];
+    normalize(x::find_solutions(m, &partial, &|m: &Instance| {
+        exact_cover::choose_nonzero_col(m).unwrap_or(0)
+    }))
+}
+
+fn solve_x_in_place(m: &Instance) -> Solns {
+    let mut solns = vec![];
+    x::solutions(m, x::choose_min_rows).gen(|s| solns.push(s.into_owned()));
+    normalize(solns)
+}
+
+fn solve_dlx(m: &Instance) -> Solns {
+    let mut d = dlx_matrix::new(m);
+    let mut solns = vec![];
+    Dlx::new(&mut d).gen(|s| solns.push(s.into_owned()));
+    normalize(solns)
+}
+
+/// Every set of rows that covers each column exactly once.  Rows of
+/// zeros are left out, since they add nothing to a cover (and the
+/// real solvers never choose them).
+fn solve_brute_force(m: &Instance) -> Solns {
+    let rows: Vec<usize> = (0..m.num_rows()).filter(|&r| m.row(r).iter().any(|&b| b)).collect();
+    let mut solns = vec![];
+    for mask in 0..(1u64 << rows.len()) {
+        let chosen: Vec<usize> = (0..rows.len())
+            .filter(|&i| mask & (1 << i) != 0)
+            .map(|i| rows[i])
+            .collect();
+        let exact = (0..m.num_cols()).all(|c| {
+            chosen.iter().filter(|&&r| BitMatrix::at(m, c, r)).count() == 1
+        });
+        if exact {
+            solns.push(chosen.iter().map(|&r| m.row_label(r).clone()).collect());
+        }
+    }
+    normalize(solns)
+}
+
+/// The name of the first solver that disagrees with brute force on
+/// `m`, if any.
+fn disagreement(m: &Instance) -> Option<&'static str> {
+    let want = solve_brute_force(m);
+    let solvers: [(&'static str, Solver); 3] =
+        [("x", solve_x), ("x in place", solve_x_in_place), ("dlx", solve_dlx)];
+    solvers.iter().find(|&&(_, solve)| solve(m) != want).map(|&(name, _)| name)
+}
+
+/// Removes rows and columns from `m` for as long as `fails` still
+/// holds of what is left.
+fn shrink<P:Fn(&Instance) -> bool>(m: &Instance, fails: P) -> Instance {
+    let mut m = m.clone();
+    'progress: loop {
+        for r in 0..m.num_rows() {
+            let smaller = m.without_row(r);
+            if fails(&smaller) { m = smaller; continue 'progress; }
+        }
+        for c in 0..m.num_cols() {
+            let smaller = m.without_col(c);
+            if fails(&smaller) { m = smaller; continue 'progress; }
+        }
+        return m;
+    }
+}
+
+#[test]
+fn cross_check_random() {
+    for seed in 0..2000 {
+        let m = random_instance(seed);
+        if let Some(name) = disagreement(&m) {
+            let small = shrink(&m, |m| disagreement(m).is_some());
+            panic!("seed {}: {} disagrees with brute force on {}\n\
+                    brute force: {:?}\nx: {:?}\nx in place: {:?}\ndlx: {:?}",
+                   seed, name, small, solve_brute_force(&small),
+                   solve_x(&small), solve_x_in_place(&small), solve_dlx(&small));
+        }
+    }
+}
+
+#[test]
+fn cross_check_shrinks() {
+    // Pretend that solvers go wrong whenever one row has three ones
+    // and there is more than one row; shrinking should find a
+    // two-row, three-column witness.
+    let fails = |m: &Instance| {
+        m.num_rows() > 1 &&
+            (0..m.num_rows()).any(|r| m.row(r).iter().filter(|&&b| b).count() >= 3)
+    };
+    let m = (0..).map(random_instance).find(|m| fails(m)).unwrap();
+    let small = shrink(&m, fails);
+    assert_eq!((small.num_rows(), small.num_cols()), (2, 3));
+    assert!(fails(&small));
+}
+
+#[test]
+fn cross_check_preprocess() {
+    // Reducing an instance (with some of its columns secondary) must
+    // not change its solutions, once the forced rows are added back.
+    for seed in 0..2000 {
+        let m = random_instance(seed);
+        let secondary: Vec<bool> = (0..m.num_cols()).map(|c| (seed >> c) % 5 == 0).collect();
+        let mut d = dlx_matrix::with_secondary(&m, |c| secondary[c]);
+        let mut want = vec![];
+        Dlx::new(&mut d).gen(|s| want.push(s.into_owned()));
+
+        let r = pre::reduce(&m, |c| secondary[c]);
+        let mut got = vec![];
+        if r.is_feasible() {
+            let mut d = dlx_matrix::with_secondary(r.matrix(), |c| r.is_secondary(c));
+            Dlx::new(&mut d).gen(|s| got.push(r.lift(&s)));
+        }
+        assert_eq!(normalize(got), normalize(want),
+                   "seed {}: {} with secondary {:?}, removals {:?}",
+                   seed, m, secondary, r.removals());
+    }
+}
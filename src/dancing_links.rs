@@ -1,51 +1,75 @@
-#![feature(phase)]
-#[phase(plugin, link)] extern crate log;
-extern crate debug;
+#![allow(dead_code, non_snake_case, non_camel_case_types)]
 
-use std::fmt;
+use std::borrow::Cow;
+use std::cmp::max;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
 
-mod exact_cover;
+use exact_cover;
+use generators::n_tuples::{Control, Generator};
+use zdd::{self, Zdd};
 
 /// A Df is a reference to a DataObj owned by the matrix.
-#[deriving(PartialEq)]
-struct Df(uint);
+#[derive(Copy, Clone, PartialEq, Debug)]
+struct Df(usize);
 
 /// A Cf is a reference to a ColumnObj owned by the matrix.
-#[deriving(PartialEq)]
-struct Cf(uint);
+#[derive(Copy, Clone, PartialEq, Debug)]
+struct Cf(usize);
 
 /// A DC is reference to either a DataObj or a ColumnObj.
-#[deriving(PartialEq)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 enum DC { Ddc(Df), Cdc(Cf), }
 
 /// A CR is a reference to either a ColumnObj or the Root sentinel node.
-#[deriving(PartialEq)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 enum CR { Ccr(Cf), Rootcr, }
 
-struct DataObj { L: Df, R: Df, U: DC, D: DC, C: Cf }
-struct ColumnObj<Label> { L: CR, R: CR, U: DC, D: DC, C: Cf, S: uint, N: Label }
+use self::DC::{Ddc, Cdc};
+use self::CR::{Ccr, Rootcr};
+
+/// `row` is the index (into `dlx_matrix.rows`) of the input row that
+/// this entry came from; it lets us report solutions by row label.
+///
+/// `color` follows Algorithm C: zero means the entry is uncolored,
+/// a positive value is the entry's color, and a negative value marks
+/// an entry whose column has been purified to that same color.
+#[derive(Copy, Clone, Debug)]
+struct DataObj { L: Df, R: Df, U: DC, D: DC, C: Cf, row: usize, color: isize }
+/// `bound` and `slack` are the multiplicity fields of Algorithm M:
+/// `bound` is how many more rows may still cover the column, and
+/// `slack` is the difference between its upper and lower limits.
+#[derive(Clone)]
+struct ColumnObj<Label> { L: CR, R: CR, U: DC, D: DC, C: Cf, S: usize, N: Label,
+                          bound: usize, slack: usize }
+#[derive(Copy, Clone)]
 struct RootObj { L: CR, R: CR }
 
-struct dlx_matrix<L> {
+#[derive(Clone)]
+pub struct dlx_matrix<L> {
     data: Vec<DataObj>,
     cols: Vec<ColumnObj<L>>,
+    rows: Vec<L>,
     root: RootObj,
 }
 
 impl Df {
-    fn L<LBL>(&Df(i): &Self, m: &dlx_matrix<LBL>) -> Df { m.data[i].L }
-    fn R<LBL>(&self, m: &dlx_matrix<LBL>) -> Df { m.data[**self].R }
-    fn D<LBL>(&self, m: &dlx_matrix<LBL>) -> DC { m.data[**self].D }
-    fn U<LBL>(&self, m: &dlx_matrix<LBL>) -> DC { m.data[**self].U }
-    fn C<LBL>(&self, m: &dlx_matrix<LBL>) -> Cf { m.data[**self].C }
+    fn L<LBL>(&self, m: &dlx_matrix<LBL>) -> Df { m.data[self.0].L }
+    fn R<LBL>(&self, m: &dlx_matrix<LBL>) -> Df { m.data[self.0].R }
+    fn D<LBL>(&self, m: &dlx_matrix<LBL>) -> DC { m.data[self.0].D }
+    fn U<LBL>(&self, m: &dlx_matrix<LBL>) -> DC { m.data[self.0].U }
+    fn C<LBL>(&self, m: &dlx_matrix<LBL>) -> Cf { m.data[self.0].C }
+    fn color<LBL>(&self, m: &dlx_matrix<LBL>) -> isize { m.data[self.0].color }
 }
 
 impl Cf {
-    fn L<LBL>(&self, m: &dlx_matrix<LBL>) -> CR { m.cols[**self].L }
-    fn R<LBL>(&self, m: &dlx_matrix<LBL>) -> CR { m.cols[**self].R }
-    fn D<LBL>(&self, m: &dlx_matrix<LBL>) -> DC { m.cols[**self].D }
-    fn U<LBL>(&self, m: &dlx_matrix<LBL>) -> DC { m.cols[**self].U }
-    fn S<'a, LBL>(&self, m: &'a mut dlx_matrix<LBL>) -> &'a mut uint { &mut m.cols[**self].S }
+    fn L<LBL>(&self, m: &dlx_matrix<LBL>) -> CR { m.cols[self.0].L }
+    fn R<LBL>(&self, m: &dlx_matrix<LBL>) -> CR { m.cols[self.0].R }
+    fn D<LBL>(&self, m: &dlx_matrix<LBL>) -> DC { m.cols[self.0].D }
+    fn U<LBL>(&self, m: &dlx_matrix<LBL>) -> DC { m.cols[self.0].U }
+    fn S<'a, LBL>(&self, m: &'a mut dlx_matrix<LBL>) -> &'a mut usize { &mut m.cols[self.0].S }
 }
 
 trait LRLinked<Ctxt, Rf> {
@@ -65,86 +89,114 @@ trait QuadLinked<Ctxt, LRf, UDf> : LRLinked<Ctxt, LRf>
 
 impl<L> LRLinked<dlx_matrix<L>, CR> for CR {
     fn update_l(&self, m: &mut dlx_matrix<L>, new_l: CR) {
-        match self {
-            &Rootcr     => { m.root.L = new_l; }
-            &Ccr(Cf(i)) => { m.cols[i].L = new_l; }
+        match *self {
+            Rootcr     => { m.root.L = new_l; }
+            Ccr(Cf(i)) => { m.cols[i].L = new_l; }
         }
     }
     fn update_r(&self, m: &mut dlx_matrix<L>, new_r: CR) {
-        match self {
-            &Rootcr     => { m.root.R = new_r; }
-            &Ccr(Cf(i)) => { m.cols[i].R = new_r; }
+        match *self {
+            Rootcr     => { m.root.R = new_r; }
+            Ccr(Cf(i)) => { m.cols[i].R = new_r; }
         }
     }
 }
 impl<L> UDLinked<dlx_matrix<L>, DC> for CR {
     fn update_u(&self, m: &mut dlx_matrix<L>, new_u: DC) {
-        match self {
-            &Rootcr     => fail!(),
-            &Ccr(Cf(i)) => { m.cols[i].U = new_u; }
+        match *self {
+            Rootcr     => panic!(),
+            Ccr(Cf(i)) => { m.cols[i].U = new_u; }
         }
     }
     fn update_d(&self, m: &mut dlx_matrix<L>, new_d: DC) {
-        match self {
-            &Rootcr     => fail!(),
-            &Ccr(Cf(i)) => { m.cols[i].D = new_d; }
+        match *self {
+            Rootcr     => panic!(),
+            Ccr(Cf(i)) => { m.cols[i].D = new_d; }
         }
     }
 }
 impl<L> UDLinked<dlx_matrix<L>, DC> for DC {
     fn update_u(&self, m: &mut dlx_matrix<L>, new_u: DC) {
-        match self {
-            &Ddc(Df(i)) => { m.data[i].U = new_u; }
-            &Cdc(Cf(i)) => { m.cols[i].U = new_u; }
+        match *self {
+            Ddc(Df(i)) => { m.data[i].U = new_u; }
+            Cdc(Cf(i)) => { m.cols[i].U = new_u; }
         }
     }
     fn update_d(&self, m: &mut dlx_matrix<L>, new_d: DC) {
-        match self {
-            &Ddc(Df(i)) => { m.data[i].D = new_d; }
-            &Cdc(Cf(i)) => { m.cols[i].D = new_d; }
+        match *self {
+            Ddc(Df(i)) => { m.data[i].D = new_d; }
+            Cdc(Cf(i)) => { m.cols[i].D = new_d; }
         }
     }
 }
 
 impl<L> LRLinked<dlx_matrix<L>, Df> for Df {
     fn update_l(&self, m: &mut dlx_matrix<L>, new_l: Df) {
-        m.data[**self].L = new_l;
+        m.data[self.0].L = new_l;
     }
     fn update_r(&self, m: &mut dlx_matrix<L>, new_r: Df) {
-        m.data[**self].R = new_r;
+        m.data[self.0].R = new_r;
     }
 }
 
 impl<L> LRLinked<dlx_matrix<L>, CR> for Cf {
     fn update_l(&self, m: &mut dlx_matrix<L>, new_l: CR) {
-        m.cols[**self].L = new_l;
+        m.cols[self.0].L = new_l;
     }
     fn update_r(&self, m: &mut dlx_matrix<L>, new_r: CR) {
-        m.cols[**self].R = new_r;
+        m.cols[self.0].R = new_r;
     }
 }
 
 impl<L> UDLinked<dlx_matrix<L>, DC> for Df {
     fn update_u(&self, m: &mut dlx_matrix<L>, new_u: DC) {
-        m.data[**self].U = new_u;
+        m.data[self.0].U = new_u;
     }
     fn update_d(&self, m: &mut dlx_matrix<L>, new_d: DC) {
-        m.data[**self].D = new_d;
+        m.data[self.0].D = new_d;
     }
 }
 
-impl<L:Clone,M:exact_cover::BitMatrix+exact_cover::ColLabelled<L>+exact_cover::RowLabelled<L>> dlx_matrix<L> {
-    fn new(input: &M) -> dlx_matrix<L> {
-        use exact_cover::Matrix;
+impl<L:Clone> dlx_matrix<L> {
+    pub fn new<M>(input: &M) -> dlx_matrix<L>
+        where M: exact_cover::BitMatrix+exact_cover::ColLabelled<L>+exact_cover::RowLabelled<L>
+    {
+        dlx_matrix::with_secondary(input, |_| false)
+    }
+
+    /// Like `new`, but every column `c` with `is_secondary(c)` is a
+    /// secondary item: a solution may cover it at most once, rather
+    /// than exactly once.
+    ///
+    /// Secondary columns are left out of the root's list (their L
+    /// and R links point back at themselves, as in Knuth's
+    /// generalized exact cover), so the search never branches on
+    /// them, but rows that use them still cover and uncover them.
+    pub fn with_secondary<M, P>(input: &M, is_secondary: P) -> dlx_matrix<L>
+        where M: exact_cover::BitMatrix+exact_cover::ColLabelled<L>+exact_cover::RowLabelled<L>,
+              P: Fn(usize) -> bool
+    {
+        dlx_matrix::with_colors(input, is_secondary, |_, _| None)
+    }
 
+    /// Like `with_secondary`, but `color(c, r)` gives the color (if
+    /// any) of the entry at column `c` of row `r`; this sets up the
+    /// color-controlled exact cover problem (XCC) of Algorithm C.
+    ///
+    /// Rows that give a secondary column the same color may share
+    /// it in a solution; an uncolored entry still claims its column
+    /// exclusively. Only entries in secondary columns may be colored.
+    pub fn with_colors<M, P, C>(input: &M, is_secondary: P, color: C) -> dlx_matrix<L>
+        where M: exact_cover::BitMatrix+exact_cover::ColLabelled<L>+exact_cover::RowLabelled<L>,
+              P: Fn(usize) -> bool,
+              C: Fn(usize, usize) -> Option<usize>
+    {
         let mut m : dlx_matrix<L> = dlx_matrix::<L> {
-            data: vec![], cols: vec![], root: RootObj { L: Rootcr, R: Rootcr }
+            data: vec![], cols: vec![], rows: vec![], root: RootObj { L: Rootcr, R: Rootcr }
         };
 
-        let mut col_labels = vec![];
-        for i in range(0, input.num_cols()) {
+        for i in 0..input.num_cols() {
             let l : L = input.col_label(i).clone();
-            col_labels.push(l.clone());
 
             //// Yucko, had to manually inline body of `prepend_empty_col`
             //// to placate type-checker.  Reduce to something small later.
@@ -158,95 +210,75 @@ impl<L:Clone,M:exact_cover::BitMatrix+exact_cover::ColLabelled<L>+exact_cover::R
                 D: dc,
                 C: Cf(idx),
                 S: 0,
-                N: l
+                N: l,
+                bound: 1,
+                slack: 0,
             };
             m.cols.push(col_obj);
-            let root_r = m.root.R;
             let cr = Ccr(Cf(idx));
+            if is_secondary(i) {
+                cr.update_l(&mut m, cr);
+                cr.update_r(&mut m, cr);
+                continue;
+            }
+            let root_r = m.root.R;
             root_r.update_l(&mut m, cr);
             Rootcr.update_r(&mut m, cr);
         }
 
-        // As we scan the input, `ptrs` keeps track of how its
-        // elements map to entries in the matrix `m` we are building.
-
-        type Ptrs<L> = Matrix<L, Option<Df>>;
-        let mut ptrs = {
-            let init_ptrs : Vec<Option<Df>> = vec![];
-            let mut ptrs = Matrix {
-                col_indent: "".into_owned(),
-                cols: col_labels,
-                rows: vec![],
-                elems: init_ptrs
-            };
-
-            for _ in range(0, input.num_rows()) {
-                for _ in range(0, input.num_cols()) {
-                    ptrs.elems.push(None); // placeholders
-                }
-            }
-
-            ptrs
-        };
-
-        fn find<L>(m: &Ptrs<L>, col: uint, row: uint, dir: int) -> Option<Df> {
-            let update = |x:uint| {
-                let next = (x as int + dir);
-                if next == -1 { m.cols.len() - 1 }
-                    else if next as uint == m.cols.len() { 0u }
-                    else { next as uint }
-            };
-            let mut cursor = update(col);
-            while cursor != col {
-                match m.at(cursor, row) {
-                    &Some(df) => return Some(df),
-                    &None     => {},
-                }
-                cursor = update(cursor);
-
-            }
-            return None;
-        }
-
-        for col in range(0, input.num_cols()) {
-            debug!("transcribing col: {:?}", *input.col_label(col));
-            let cf = Cf(col);
-            let hdr = Cdc(cf);
-            let mut last_in_col : DC = hdr;
-            for row in range(0, input.num_rows()) {
-                if input.at(col, row) {
-                    debug!("transcribing entry: ({:?},{:?})", *input.col_label(col), *input.row_label(row));
-                    let l = find(&ptrs, col, row, -1);
-                    let r = find(&ptrs, col, row,  1);
-
-                    debug!("entry: ({:?},{:?}) found l: {:?} r: {:?}",
-                           *input.col_label(col), *input.row_label(row), l, r);
-
-                    let df = Df(m.data.len());
-                    let l = l.unwrap_or(df);
-                    let r = r.unwrap_or(df);
+        // We transcribe the input a row at a time, asking it only for
+        // the columns each row has on; a sparse input then costs time
+        // proportional to its number of ones.  Each new entry goes at
+        // the bottom of its column (the header's U link) and at the
+        // end of its row's circular list.
 
-                    let d = DataObj { L: l, R: r, U: last_in_col, D: hdr, C: cf };
-                    debug!("entry: ({:?},{:?}) gets obj {:?}",
-                           *input.col_label(col), *input.row_label(row), d);
-                    let df = Df(m.data.len());
-                    m.data.push(d);
+        for row in 0..input.num_rows() {
+            m.rows.push(input.row_label(row).clone());
+            let cols: Vec<usize> = input.cols_on(row, &exact_cover::Accumulate);
+            let first = Df(m.data.len());
+            for &col in cols.iter() {
+                debug!("transcribing entry: ({},{})", col, row);
+                let cf = Cf(col);
+                let hdr = Cdc(cf);
+                let last_in_col = m.cols[col].U;
+                let df = Df(m.data.len());
+                let l = if df == first { df } else { Df(df.0 - 1) };
 
-                    l.update_r(&mut m, df);
-                    r.update_l(&mut m, df);
-                    last_in_col.update_d(&mut m, Ddc(df));
-                    hdr.update_u(&mut m, Ddc(df));
+                let color = match color(col, row) {
+                    None => 0,
+                    Some(k) => {
+                        assert!(is_secondary(col),
+                                "only secondary columns can be colored");
+                        k as isize + 1
+                    }
+                };
+                let d = DataObj { L: l, R: first, U: last_in_col, D: hdr, C: cf,
+                                  row, color };
+                debug!("entry: ({},{}) gets obj {:?}", col, row, d);
+                m.data.push(d);
+                *cf.S(&mut m) += 1;
 
-                    ptrs.put(col, row, Some(df));
-                    last_in_col = Ddc(df);
-                }
+                l.update_r(&mut m, df);
+                first.update_l(&mut m, df);
+                last_in_col.update_d(&mut m, Ddc(df));
+                hdr.update_u(&mut m, Ddc(df));
             }
         }
 
         m
     }
 
-    #[cfg(not_now)]
+    /// Requires every solution to cover primary column `col` with at
+    /// least `lo` and at most `hi` rows, as in Algorithm M. Columns
+    /// start out with `lo == hi == 1`, i.e. ordinary exact cover.
+    pub fn set_bounds(&mut self, col: usize, lo: usize, hi: usize) {
+        assert!(lo <= hi && hi > 0, "bounds must satisfy 0 <= lo <= hi, 0 < hi");
+        assert!(self.cols[col].L != Ccr(Cf(col)),
+                "only primary columns can have bounds");
+        self.cols[col].bound = hi;
+        self.cols[col].slack = hi - lo;
+    }
+
     fn prepend_empty_col(&mut self, label: L) -> Cf {
         let idx = self.cols.len();
         let dc = Cdc(Cf(idx));
@@ -257,7 +289,9 @@ impl<L:Clone,M:exact_cover::BitMatrix+exact_cover::ColLabelled<L>+exact_cover::R
             D: dc,
             C: Cf(idx),
             S: 0,
-            N: label
+            N: label,
+            bound: 1,
+            slack: 0,
         };
         self.cols.push(col_obj);
         let root_r = self.root.R;
@@ -268,38 +302,770 @@ impl<L:Clone,M:exact_cover::BitMatrix+exact_cover::ColLabelled<L>+exact_cover::R
     }
 }
 
-struct Dlx<'a, L> {
+impl<L> dlx_matrix<L> {
+    /// The primary columns still to be dealt with, in the order they
+    /// are linked from the root.
+    pub fn active_cols<'a>(&'a self) -> ActiveCols<'a, L> {
+        ActiveCols { m: self, cursor: self.root.R }
+    }
+
+    pub fn col_label(&self, col: usize) -> &L { &self.cols[col].N }
+
+    pub fn row_label(&self, row: usize) -> &L { &self.rows[row] }
+
+    /// The number of rows currently in column `col`.
+    pub fn col_len(&self, col: usize) -> usize { self.cols[col].S }
+
+    /// The number of ways Algorithm M can branch on column `col`: one
+    /// per row still in the column, plus one for choosing no more
+    /// rows, less however many rows the column still needs. For an
+    /// ordinary exact cover column this is just `col_len(col)`.
+    pub fn branching_degree(&self, col: usize) -> isize {
+        let c = &self.cols[col];
+        let need = c.bound as isize - c.slack as isize;
+        c.S as isize + 1 - max(need, 0)
+    }
+
+    fn is_active(&self, c: Cf) -> bool {
+        match c.L(self) {
+            Rootcr => self.root.R == Ccr(c),
+            Ccr(l) => l.R(self) == Ccr(c),
+        }
+    }
+}
+
+pub struct ActiveCols<'a, L:'a> { m: &'a dlx_matrix<L>, cursor: CR }
+
+impl<'a, L> Iterator for ActiveCols<'a, L> {
+    type Item = usize;
+    fn next(&mut self) -> Option<usize> {
+        match self.cursor {
+            Rootcr => None,
+            Ccr(cf) => {
+                self.cursor = cf.R(self.m);
+                Some(cf.0)
+            }
+        }
+    }
+}
+
+/// Picks the column that `Dlx` branches on next.
+///
+/// `choose` is only called when at least one column is active, and
+/// must return one of `m.active_cols()`. It takes `&mut self` so
+/// that a chooser can carry state from one call to the next.
+pub trait ColChooser<L> {
+    fn choose(&mut self, m: &dlx_matrix<L>, level: usize) -> usize;
+}
+
+impl<L, F> ColChooser<L> for F where F: FnMut(&dlx_matrix<L>, usize) -> usize {
+    fn choose(&mut self, m: &dlx_matrix<L>, level: usize) -> usize {
+        self(m, level)
+    }
+}
+
+/// Always takes the first active column.
+pub struct FirstCol;
+
+impl<L> ColChooser<L> for FirstCol {
+    fn choose(&mut self, m: &dlx_matrix<L>, _level: usize) -> usize {
+        match m.active_cols().next() {
+            Some(c) => c,
+            None => panic!("should not choose col on empty matrix")
+        }
+    }
+}
+
+/// Takes the first active column of minimum branching degree; this
+/// is Knuth's "minimum remaining values" heuristic.
+pub struct Mrv;
+
+impl<L> ColChooser<L> for Mrv {
+    fn choose(&mut self, m: &dlx_matrix<L>, _level: usize) -> usize {
+        let mut c = None;
+        let mut s = None; // represents +infinity
+        for j in m.active_cols() {
+            match (m.branching_degree(j), s) {
+                (s2, None)                => { s = Some(s2); c = Some(j); }
+                (s2, Some(s1)) if s2 < s1 => { s = Some(s2); c = Some(j); }
+                _ => {} }
+        }
+
+        match c {
+            Some(c) => c,
+            None => panic!("should not choose col on empty matrix")
+        }
+    }
+}
+
+/// Like `Mrv`, but breaks ties between columns of minimum branching
+/// degree uniformly at random, from a seeded generator so that runs
+/// can be reproduced.
+pub struct MrvRandomTies { rng: XorShift }
+
+impl MrvRandomTies {
+    pub fn new(seed: u64) -> MrvRandomTies {
+        MrvRandomTies { rng: XorShift::new(seed) }
+    }
+}
+
+impl<L> ColChooser<L> for MrvRandomTies {
+    fn choose(&mut self, m: &dlx_matrix<L>, _level: usize) -> usize {
+        let mut c = None;
+        let mut s = None; // represents +infinity
+        let mut ties = 0;
+        for j in m.active_cols() {
+            let s2 = m.branching_degree(j);
+            match s {
+                Some(s1) if s2 > s1 => continue,
+                Some(s1) if s2 == s1 => ties += 1,
+                _ => { s = Some(s2); ties = 1; }
+            }
+            // Reservoir sampling: the i'th tie replaces the current
+            // choice with probability 1/i.
+            if self.rng.below(ties) == 0 {
+                c = Some(j);
+            }
+        }
+
+        match c {
+            Some(c) => c,
+            None => panic!("should not choose col on empty matrix")
+        }
+    }
+}
+
+/// Marsaglia's xorshift64* generator; plenty for randomizing the
+/// search, and it keeps us free of external dependencies.
+#[derive(Clone, Debug)]
+struct XorShift { state: u64 }
+
+impl XorShift {
+    fn new(seed: u64) -> XorShift {
+        // The all-zeroes state is a fixed point, so steer clear of it.
+        XorShift { state: seed ^ 0x9E37_79B9_7F4A_7C15 }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// A value in `0..n`; `n` must be positive.
+    fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+}
+
+/// Counters gathered over a run of `Dlx`, for comparing heuristics.
+///
+/// `nodes[k]` counts the times the search entered level `k` (the
+/// root being level 0), and `updates[k]` counts the link updates
+/// ("mems" of Knuth's cost model, roughly) made while choosing rows
+/// at level `k`.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct SearchStats {
+    pub solutions: u64,
+    pub nodes: Vec<u64>,
+    pub updates: Vec<u64>,
+    pub max_depth: usize,
+}
+
+impl SearchStats {
+    fn enter_level(&mut self, k: usize) {
+        self.reach(k);
+        self.nodes[k] += 1;
+        self.max_depth = max(self.max_depth, k);
+    }
+
+    fn add_update(&mut self, k: usize) {
+        self.reach(k);
+        self.updates[k] += 1;
+    }
+
+    fn reach(&mut self, k: usize) {
+        while self.nodes.len() <= k {
+            self.nodes.push(0);
+            self.updates.push(0);
+        }
+    }
+
+    /// Adds in the counts of `other`, a search of a disjoint part of
+    /// the same tree.
+    pub fn merge(&mut self, other: &SearchStats) {
+        if !other.nodes.is_empty() {
+            self.reach(other.nodes.len() - 1);
+        }
+        for (k, (&n, &u)) in other.nodes.iter().zip(other.updates.iter()).enumerate() {
+            self.nodes[k] += n;
+            self.updates[k] += u;
+        }
+        self.solutions += other.solutions;
+        self.max_depth = max(self.max_depth, other.max_depth);
+    }
+
+    pub fn total_nodes(&self) -> u64 { self.nodes.iter().sum() }
+    pub fn total_updates(&self) -> u64 { self.updates.iter().sum() }
+}
+
+/// The results of `Dlx::estimate`: means and (sample) variances of
+/// the per-probe estimates of the tree's node and solution counts.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Estimate {
+    pub probes: u64,
+    pub nodes: f64,
+    pub nodes_variance: f64,
+    pub solutions: f64,
+    pub solutions_variance: f64,
+}
+
+/// Welford's running mean and variance (TAOCP 4.2.2, eq. (15)).
+#[derive(Default)]
+struct Welford { n: u64, mean: f64, m2: f64 }
+
+impl Welford {
+    fn add(&mut self, x: f64) {
+        self.n += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.n as f64;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    fn variance(&self) -> f64 {
+        if self.n < 2 { 0.0 } else { self.m2 / (self.n - 1) as f64 }
+    }
+}
+
+/// Knuth's Algorithm X, implemented via dancing links.
+///
+/// A `Dlx` is a `Generator` over the solutions of its matrix; each
+/// solution is handed to the visitor as the list of its row labels.
+/// The matrix is restored to its original state when the search
+/// finishes, even if the visitor breaks out early.
+///
+/// The search keeps its own stack of levels rather than recursing,
+/// so it can also be driven a step at a time (see `step`), and its
+/// position saved as a `Snapshot` and later resumed from.
+pub struct Dlx<'a, L:'a> {
     m: &'a mut dlx_matrix<L>,
     soln: Vec<Df>,
-    count_updates: uint,
+    stack: Vec<Frame>,
+    phase: Phase,
+    /// The search is over once it backtracks past this many levels;
+    /// nonzero only when exploring a subtree (see `resume_subtree`).
+    floor: usize,
+    /// When set, the search pauses instead of entering this level.
+    split_depth: Option<usize>,
+    level: usize,
+    stats: SearchStats,
+}
+
+/// What the search does when it next gets control.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Phase {
+    /// Enter level `stack.len()`, as Knuth's step X2.
+    Enter,
+    /// Try the next way of branching at the top frame.
+    Next,
+    /// Undo the top frame's current branch.
+    Backtrack,
+    Done,
+}
+
+/// The state of one level of the search.
+struct Frame {
+    col: Cf,
+    /// How many more rows `col` needed when this level was entered.
+    need: isize,
+    /// If `col` was covered outright, rather than having its rows
+    /// tweaked out of it one at a time (into `tweaked`).
+    covered: bool,
+    tweaked: Vec<Df>,
+    choice: Choice,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum Choice {
+    Start,
+    /// The row containing this entry is in the solution.
+    Row(Df),
+    /// No more rows are to cover `col`.
+    Rest,
+}
+
+/// Why `Dlx::step` returned.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Step {
+    /// A solution has been reached; see `Dlx::solution`.
+    Solution,
+    /// The node budget ran out.
+    Paused,
+    /// The search is over and the matrix has been restored.
+    Done,
 }
 
-impl<'a, L:fmt::String> Dlx<'a, L> {
+impl<'a, L> Dlx<'a, L> {
+    pub fn new(m: &'a mut dlx_matrix<L>) -> Dlx<'a, L> {
+        Dlx { m, soln: vec![], stack: vec![], phase: Phase::Enter,
+              floor: 0, split_depth: None, level: 0, stats: SearchStats::default() }
+    }
+
+    pub fn count_updates(&self) -> usize { self.stats.total_updates() as usize }
+
+    /// The statistics accumulated by every search run so far.
+    pub fn stats(&self) -> &SearchStats { &self.stats }
+
+    /// Runs the whole search without reporting individual solutions,
+    /// returning the statistics for that run alone.
+    pub fn count(&mut self) -> SearchStats {
+        self.count_with(&mut Mrv)
+    }
+
+    /// Like `count`, branching on the columns that `chooser` picks.
+    pub fn count_with<C:ColChooser<L>>(&mut self, chooser: &mut C) -> SearchStats {
+        self.reset();
+        self.stats = SearchStats::default();
+        while self.step(chooser, u64::MAX) != Step::Done { }
+        self.stats.clone()
+    }
+
+    /// Advances the search until it reaches a solution, enters
+    /// `max_nodes` nodes, or runs out of nodes to visit; calling
+    /// `step` again carries on from where it left off.
+    pub fn step<C:ColChooser<L>>(&mut self, chooser: &mut C, max_nodes: u64) -> Step {
+        let mut nodes = 0;
+        loop {
+            match self.phase {
+                Phase::Enter => {
+                    if nodes == max_nodes || Some(self.stack.len()) == self.split_depth {
+                        return Step::Paused;
+                    }
+                    nodes += 1;
+                    let k = self.stack.len();
+                    self.level = k;
+                    self.stats.enter_level(k);
+                    if self.m.root.R == Rootcr {
+                        self.stats.solutions += 1;
+                        self.phase = Phase::Backtrack;
+                        return Step::Solution;
+                    }
+                    let c = Cf(chooser.choose(self.m, k));
+                    assert!(self.m.is_active(c), "chooser must pick an active column");
+                    self.phase = if self.open_frame(c) { Phase::Next } else { Phase::Backtrack };
+                }
+                Phase::Next => {
+                    self.phase = self.advance();
+                }
+                Phase::Backtrack => {
+                    if self.stack.len() <= self.floor {
+                        self.reset();
+                        self.phase = Phase::Done;
+                    } else {
+                        self.retreat();
+                        self.phase = Phase::Next;
+                    }
+                }
+                Phase::Done => return Step::Done,
+            }
+        }
+    }
+
+    /// Abandons any search in progress, restoring the matrix, so that
+    /// the next search starts from the beginning.
+    pub fn reset(&mut self) {
+        while !self.stack.is_empty() {
+            if self.stack.last().unwrap().choice != Choice::Start {
+                self.retreat();
+            }
+            self.close_frame();
+        }
+        self.phase = Phase::Enter;
+        self.floor = 0;
+    }
+
+    /// The position of the search, to be passed to `resume`.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            levels: self.stack.iter().map(|f| {
+                let row = match f.choice {
+                    Choice::Row(r) => Some(self.m.data[r.0].row),
+                    Choice::Rest => None,
+                    Choice::Start => panic!("no snapshot in mid-step"),
+                };
+                (f.col.0, row)
+            }).collect(),
+            phase: self.phase,
+        }
+    }
+
+    /// Puts the search back at the position `snap` was taken at, so
+    /// that `step` continues from there. `snap` must have come from a
+    /// `Dlx` over an identical matrix; if it cannot have, this is an
+    /// error, and the search is left at its start instead. Search
+    /// statistics are not part of a snapshot and are left alone.
+    pub fn resume(&mut self, snap: &Snapshot) -> io::Result<()> {
+        self.reset();
+        let resumed = self.replay(snap);
+        if resumed.is_err() {
+            self.reset();
+        }
+        resumed
+    }
+
+    fn replay(&mut self, snap: &Snapshot) -> io::Result<()> {
+        fn mismatch(level: usize) -> io::Error {
+            io::Error::new(io::ErrorKind::InvalidData,
+                           format!("snapshot does not match this matrix at level {}", level))
+        }
+        for (k, &(col, row)) in snap.levels.iter().enumerate() {
+            self.level = k;
+            let c = Cf(col);
+            if col >= self.m.cols.len() || !self.m.is_active(c) || !self.open_frame(c) {
+                return Err(mismatch(k));
+            }
+            let covered = self.stack[k].covered;
+            let choice = loop {
+                let d = match (covered, self.stack[k].choice) {
+                    (true, Choice::Row(r)) => r.D(self.m),
+                    _ => c.D(self.m),
+                };
+                match d {
+                    Ddc(r) => {
+                        if !covered {
+                            self.tweak(r);
+                            self.stack[k].tweaked.push(r);
+                        }
+                        self.stack[k].choice = Choice::Row(r);
+                        if Some(self.m.data[r.0].row) == row {
+                            self.take_row(r);
+                            break Choice::Row(r);
+                        }
+                    }
+                    Cdc(_) => {
+                        if row.is_some() || self.stack[k].need > 0 {
+                            // Nothing is taken at this level yet, so
+                            // `reset` need only close the frame.
+                            self.stack[k].choice = Choice::Start;
+                            return Err(mismatch(k));
+                        }
+                        if !covered {
+                            self.deactivate(c);
+                        }
+                        break Choice::Rest;
+                    }
+                }
+            };
+            self.stack[k].choice = choice;
+        }
+        self.phase = snap.phase;
+        Ok(())
+    }
+
+    /// Estimates the size of the search tree by Knuth's Monte Carlo
+    /// method (TAOCP 7.2.2, Theorem E): each of `probes` random walks
+    /// from the root picks a child uniformly at random at every node,
+    /// and the products of the branching degrees along the walk give
+    /// unbiased estimates of the number of nodes and of solutions.
+    /// Runs with the same `seed` and chooser make the same probes.
+    pub fn estimate<C:ColChooser<L>>(&mut self, chooser: &mut C, probes: u64, seed: u64) -> Estimate {
+        let mut rng = XorShift::new(seed);
+        let mut nodes = Welford::default();
+        let mut solutions = Welford::default();
+        for _ in 0..probes {
+            let (n, s) = self.probe(chooser, &mut rng);
+            nodes.add(n);
+            solutions.add(s);
+        }
+        Estimate {
+            probes,
+            nodes: nodes.mean,
+            nodes_variance: nodes.variance(),
+            solutions: solutions.mean,
+            solutions_variance: solutions.variance(),
+        }
+    }
+
+    /// One random walk for `estimate`, returning its estimates of the
+    /// node and solution counts.
+    fn probe<C:ColChooser<L>>(&mut self, chooser: &mut C, rng: &mut XorShift) -> (f64, f64) {
+        self.reset();
+        let mut weight = 1.0;
+        let mut nodes = 1.0;
+        let mut solutions = 0.0;
+        loop {
+            let k = self.stack.len();
+            self.level = k;
+            if self.m.root.R == Rootcr {
+                solutions = weight;
+                break;
+            }
+            let c = Cf(chooser.choose(self.m, k));
+            assert!(self.m.is_active(c), "chooser must pick an active column");
+            if !self.open_frame(c) {
+                break;
+            }
+            // The children here are the ones `advance` would visit:
+            // one per row of `c`, and one more if `c` may be left be.
+            let rows = *c.S(self.m);
+            let degree = rows + if self.stack[k].need <= 0 { 1 } else { 0 };
+            if degree == 0 {
+                break;
+            }
+            weight *= degree as f64;
+            nodes += weight;
+            let n = rng.below(degree as u64) as usize;
+            let covered = self.stack[k].covered;
+            let mut d = c.D(self.m);
+            for i in 0..n+1 {
+                match d {
+                    Ddc(r) => {
+                        if !covered {
+                            self.tweak(r);
+                            self.stack[k].tweaked.push(r);
+                        }
+                        self.stack[k].choice = Choice::Row(r);
+                        if i == n {
+                            self.take_row(r);
+                        } else if covered {
+                            d = r.D(self.m);
+                        } else {
+                            d = c.D(self.m);
+                        }
+                    }
+                    Cdc(_) => {
+                        if !covered {
+                            self.deactivate(c);
+                        }
+                        self.stack[k].choice = Choice::Rest;
+                    }
+                }
+            }
+        }
+        self.reset();
+        (nodes, solutions)
+    }
+
+    /// Builds a ZDD of every solution, as Knuth's Algorithm DXZ does:
+    /// a search like Algorithm X, except that the family of solutions
+    /// below each node is remembered, keyed by the set of columns not
+    /// yet covered, so that a subproblem met again is not solved again.
+    ///
+    /// The ZDD's variables are the indices of the input rows.  Only
+    /// plain exact cover (with secondary columns, but no colors or
+    /// multiplicities) is supported.  Since the search meets each
+    /// subproblem once, `stats().solutions` counts the solutions from
+    /// the ZDD (up to `u64::MAX`) rather than as they are found.
+    pub fn zdd<C:ColChooser<L>>(&mut self, chooser: &mut C) -> Zdd {
+        assert!(self.m.data.iter().all(|d| d.color == 0),
+                "DXZ does not handle colors");
+        assert!(self.m.cols.iter().all(|c| c.bound == 1 && c.slack == 0),
+                "DXZ does not handle multiplicities");
+        self.reset();
+        let mut b = zdd::Builder::new();
+        let mut memo = HashMap::new();
+        let n = self.m.cols.len();
+        let mut live = vec![!0u64; n.div_ceil(64)];
+        for c in n..live.len() * 64 { live[c / 64] &= !(1 << (c % 64)); }
+        let root = self.dxz(chooser, &mut b, &mut memo, &mut live);
+        let z = b.finish(root);
+        self.stats.solutions += ::std::cmp::min(z.count(), u64::MAX as u128) as u64;
+        z
+    }
+
+    fn dxz<C:ColChooser<L>>(&mut self, chooser: &mut C, b: &mut zdd::Builder,
+                            memo: &mut HashMap<Vec<u64>, zdd::Node>,
+                            live: &mut Vec<u64>) -> zdd::Node {
+        if self.m.root.R == Rootcr {
+            return zdd::TOP;
+        }
+        if let Some(&z) = memo.get(live) {
+            return z;
+        }
+        self.stats.enter_level(self.level);
+        let c = Cf(chooser.choose(self.m, self.level));
+        let mut branches = vec![];
+        self.cover(c);
+        live[c.0 / 64] &= !(1 << (c.0 % 64));
+        let mut i = c.D(self.m);
+        while let Ddc(rd) = i {
+            let mut j = rd.R(self.m);
+            while j != rd {
+                let cj = j.C(self.m);
+                self.cover(cj);
+                live[cj.0 / 64] &= !(1 << (cj.0 % 64));
+                j = j.R(self.m);
+            }
+            self.level += 1;
+            let sub = self.dxz(chooser, b, memo, live);
+            self.level -= 1;
+            let mut j = rd.L(self.m);
+            while j != rd {
+                let cj = j.C(self.m);
+                live[cj.0 / 64] |= 1 << (cj.0 % 64);
+                self.uncover(cj);
+                j = j.L(self.m);
+            }
+            if sub != zdd::BOTTOM {
+                branches.push((self.m.data[rd.0].row, sub));
+            }
+            i = rd.D(self.m);
+        }
+        live[c.0 / 64] |= 1 << (c.0 % 64);
+        self.uncover(c);
+
+        let z = branches.iter().rev().fold(zdd::BOTTOM, |lo, &(row, hi)| b.node(row, lo, hi));
+        memo.insert(live.clone(), z);
+        z
+    }
+
+    /// Like `resume`, but only explores the subtree below the node
+    /// `snap` was taken at; the search is done when that subtree is.
+    pub fn resume_subtree(&mut self, snap: &Snapshot) -> io::Result<()> {
+        self.resume(snap)?;
+        self.floor = snap.levels.len();
+        Ok(())
+    }
+
+    /// Starts branching on `c`; returns `false` if `c` cannot be
+    /// covered often enough, in which case nothing is changed.
+    fn open_frame(&mut self, c: Cf) -> bool {
+        // `need` is how many more rows must cover `c`; when it is not
+        // positive, choosing no further rows for `c` is also an option.
+        let need = self.m.cols[c.0].bound as isize - self.m.cols[c.0].slack as isize;
+        if need > *c.S(self.m) as isize {
+            return false;
+        }
+        self.m.cols[c.0].bound -= 1;
+        // If whichever row we pick exhausts `c`, then (as in Algorithm
+        // X) it can be covered outright. Otherwise `c` stays active,
+        // so each row is tweaked out of it before being tried; deeper
+        // levels can then only pick later rows of `c`, and each
+        // solution is visited once.
+        let covered = self.m.cols[c.0].bound == 0;
+        if covered {
+            self.cover(c);
+        }
+        self.stack.push(Frame { col: c, need, covered,
+                                tweaked: vec![], choice: Choice::Start });
+        true
+    }
+
+    fn close_frame(&mut self) {
+        let f = self.stack.pop().unwrap();
+        self.level = self.stack.len();
+        if f.covered {
+            self.uncover(f.col);
+        } else {
+            for &rd in f.tweaked.iter().rev() {
+                self.untweak(rd);
+            }
+        }
+        self.m.cols[f.col.0].bound += 1;
+    }
+
+    /// Moves the top frame on to its next branch, if it has one.
+    fn advance(&mut self) -> Phase {
+        let k = self.stack.len() - 1;
+        self.level = k;
+        let (c, covered, need, choice) = {
+            let f = &self.stack[k];
+            (f.col, f.covered, f.need, f.choice)
+        };
+        let next = match (covered, choice) {
+            (_, Choice::Rest) => None,
+            (true, Choice::Row(r)) => Some(r.D(self.m)),
+            _ => Some(c.D(self.m)),
+        };
+        match next {
+            Some(Ddc(rd)) => {
+                if !covered {
+                    self.tweak(rd);
+                    self.stack[k].tweaked.push(rd);
+                }
+                self.stack[k].choice = Choice::Row(rd);
+                self.take_row(rd);
+                Phase::Enter
+            }
+            Some(Cdc(_)) if need <= 0 => {
+                // Every row of `c` is out of the way by now; all that
+                // is left is to stop branching on it.
+                if !covered {
+                    self.deactivate(c);
+                }
+                self.stack[k].choice = Choice::Rest;
+                Phase::Enter
+            }
+            _ => {
+                self.close_frame();
+                Phase::Backtrack
+            }
+        }
+    }
+
+    /// Undoes the top frame's current branch.
+    fn retreat(&mut self) {
+        let k = self.stack.len() - 1;
+        self.level = k;
+        match self.stack[k].choice {
+            Choice::Row(rd) => self.drop_row(rd),
+            Choice::Rest => if !self.stack[k].covered {
+                let c = self.stack[k].col;
+                self.reactivate(c);
+            },
+            Choice::Start => panic!("nothing to retreat from"),
+        }
+    }
+
+    /// Adds the row containing `rd` to the solution.
+    fn take_row(&mut self, rd: Df) {
+        self.soln.push(rd);
+        let mut j = rd.R(self.m);
+        while j != rd {
+            self.commit(j);
+            j = j.R(self.m);
+        }
+    }
+
+    fn drop_row(&mut self, rd: Df) {
+        let popped = self.soln.pop();
+        debug_assert!(popped == Some(rd));
+        let mut j = rd.L(self.m);
+        while j != rd {
+            self.uncommit(j);
+            j = j.L(self.m);
+        }
+    }
+
+    /// Takes `c` out of the list of active columns, leaving its rows be.
+    fn deactivate(&mut self, c: Cf) {
+        let new_l = c.L(self.m);
+        c.R(self.m).update_l(self.m, new_l);
+        let new_r = c.R(self.m);
+        c.L(self.m).update_r(self.m, new_r);
+    }
+
+    fn reactivate(&mut self, c: Cf) {
+        c.R(self.m).update_l(self.m, Ccr(c));
+        c.L(self.m).update_r(self.m, Ccr(c));
+    }
 
     fn cover(&mut self, c: Cf) {
         let new_l = c.L(self.m);
         c.R(self.m).update_l(self.m, new_l);
         let new_r = c.R(self.m);
         c.L(self.m).update_r(self.m, new_r);
-        // self.count_updates += 1;
+        // self.stats.add_update(self.level);
 
         let mut i = c.D(self.m);
         loop {
             match i {
                 Cdc(_) => break,
                 Ddc(id) => {
-                    let mut j = id.R(self.m);
-                    while j != id {
-                        let new_u = j.U(self.m);
-                        j.D(self.m).update_u(self.m, new_u);
-                        let new_d = j.D(self.m);
-                        j.U(self.m).update_d(self.m, new_d);
-                        self.count_updates += 1;
-
-                        *j.C(self.m).S(self.m) -= 1;
-
-                        j = j.R(self.m);
-                    }
+                    self.hide(id);
                     i = id.D(self.m);
                 }
             }
@@ -312,13 +1078,7 @@ impl<'a, L:fmt::String> Dlx<'a, L> {
             match i {
                 Cdc(_) => break,
                 Ddc(id) => {
-                    let mut j = id.L(self.m);
-                    while j != id {
-                        *j.C(self.m).S(self.m) += 1;
-                        j.D(self.m).update_u(self.m, Ddc(j));
-                        j.U(self.m).update_d(self.m, Ddc(j));
-                        j = j.L(self.m);
-                    }
+                    self.unhide(id);
                     i = id.U(self.m);
                 }
             }
@@ -327,110 +1087,877 @@ impl<'a, L:fmt::String> Dlx<'a, L> {
         c.L(self.m).update_r(self.m, Ccr(c));
     }
 
-    fn search(&mut self,
-              k: uint,
-              select_col: &|&dlx_matrix<L>| -> Cf
-) {
-        if self.m.root.R == Rootcr {
-            self.print_soln();
-            return;
+    /// Removes every other entry of the row containing `id` from its
+    /// column. Entries already known to match a purified column are
+    /// left alone, since nothing will look at that column again
+    /// until it is unpurified.
+    fn hide(&mut self, id: Df) {
+        let mut j = id.R(self.m);
+        while j != id {
+            if j.color(self.m) >= 0 {
+                let new_u = j.U(self.m);
+                j.D(self.m).update_u(self.m, new_u);
+                let new_d = j.D(self.m);
+                j.U(self.m).update_d(self.m, new_d);
+                self.stats.add_update(self.level);
+
+                *j.C(self.m).S(self.m) -= 1;
+            }
+
+            j = j.R(self.m);
         }
-        // Does the algorithm truly mutate `c` as it runs ...?
-        let mut c = (*select_col)(self.m);
-        self.cover(c);
-        let mut r = c.D(self.m);
+    }
+
+    fn unhide(&mut self, id: Df) {
+        let mut j = id.L(self.m);
+        while j != id {
+            if j.color(self.m) >= 0 {
+                *j.C(self.m).S(self.m) += 1;
+                j.D(self.m).update_u(self.m, Ddc(j));
+                j.U(self.m).update_d(self.m, Ddc(j));
+            }
+            j = j.L(self.m);
+        }
+    }
+
+    /// Claims the column of `p` on behalf of the row containing `p`.
+    /// The column is only covered once its bound is used up.
+    fn commit(&mut self, p: Df) {
+        let color = p.color(self.m);
+        if color == 0 {
+            let c = p.C(self.m);
+            self.m.cols[c.0].bound -= 1;
+            if self.m.cols[c.0].bound == 0 {
+                self.cover(c);
+            }
+        } else if color > 0 {
+            self.purify(p);
+        }
+    }
+
+    fn uncommit(&mut self, p: Df) {
+        let color = p.color(self.m);
+        if color == 0 {
+            let c = p.C(self.m);
+            if self.m.cols[c.0].bound == 0 {
+                self.uncover(c);
+            }
+            self.m.cols[c.0].bound += 1;
+        } else if color > 0 {
+            self.unpurify(p);
+        }
+    }
+
+    /// Takes the row containing `x` out of consideration, both from
+    /// the other columns it hits and from the column of `x` itself.
+    fn tweak(&mut self, x: Df) {
+        self.hide(x);
+        let new_u = x.U(self.m);
+        x.D(self.m).update_u(self.m, new_u);
+        let new_d = x.D(self.m);
+        x.U(self.m).update_d(self.m, new_d);
+        *x.C(self.m).S(self.m) -= 1;
+    }
+
+    fn untweak(&mut self, x: Df) {
+        *x.C(self.m).S(self.m) += 1;
+        x.D(self.m).update_u(self.m, Ddc(x));
+        x.U(self.m).update_d(self.m, Ddc(x));
+        self.unhide(x);
+    }
+
+    /// Hides every row whose entry in the column of `p` has a color
+    /// other than that of `p`; the rows that agree are marked so that
+    /// committing them later is a no-op.
+    fn purify(&mut self, p: Df) {
+        let color = p.color(self.m);
+        let mut i = p.C(self.m).D(self.m);
         loop {
-            match r {
+            match i {
                 Cdc(_) => break,
-                Ddc(rd) => {
-                    assert!(self.soln.len() >= k);
-                    if self.soln.len() == k {
-                        self.soln.push(rd);
+                Ddc(q) => {
+                    if q.color(self.m) == color {
+                        self.m.data[q.0].color = -1;
                     } else {
-                        self.soln[k] = rd;
-                    }
-                    let mut j = rd.R(self.m);
-                    while j != rd {
-                        let c = j.C(self.m);
-                        self.cover(c);
-                        j = j.R(self.m);
+                        self.hide(q);
                     }
-                    self.search(k+1, select_col);
-                    rd = self.soln[k];
-                    c = rd.C(self.m); // ... is this line supposed to be here?
-                    let mut j = rd.L(self.m);
-                    while j != rd {
-                        let c = j.C(self.m);
-                        self.uncover(c);
-                        j = j.L(self.m);
+                    i = q.D(self.m);
+                }
+            }
+        }
+    }
+
+    fn unpurify(&mut self, p: Df) {
+        let color = p.color(self.m);
+        let mut i = p.C(self.m).U(self.m);
+        loop {
+            match i {
+                Cdc(_) => break,
+                Ddc(q) => {
+                    if q.color(self.m) < 0 {
+                        self.m.data[q.0].color = color;
+                    } else {
+                        self.unhide(q);
                     }
-                    r = rd.D(self.m);
+                    i = q.U(self.m);
                 }
             }
         }
-        self.uncover(c);
     }
 
-    fn print_soln(&self) {
-        for &d in self.soln.iter() {
-            self.print_row_containing(d);
-            println!("");
+}
+
+impl<'a, L> Drop for Dlx<'a, L> {
+    fn drop(&mut self) {
+        self.reset();
+    }
+}
+
+impl<'a, L:Clone> Dlx<'a, L> {
+    /// The labels of the rows in the solution just reached by `step`.
+    pub fn solution(&self) -> Vec<L> {
+        self.soln.iter()
+            .map(|&d| self.m.rows[self.m.data[d.0].row].clone())
+            .collect()
+    }
+
+    /// Like `gen`, branching on the columns that `chooser` picks.
+    pub fn gen_with<C, F, R>(&mut self, chooser: &mut C, mut visit: F)
+        where C: ColChooser<L>,
+              F: for <'b> FnMut(Cow<'b, [L]>) -> R,
+              R: Into<Control<()>>
+    {
+        self.reset();
+        while self.step(chooser, u64::MAX) == Step::Solution {
+            let labels = self.solution();
+            match visit(Cow::Borrowed(&labels[..])).into() {
+                Control::Break(()) => { self.reset(); return; }
+                Control::Yield => {}
+            }
         }
-        println!("");
     }
+}
 
-    fn print_row_containing(&self, d: Df) {
-        let mut cursor = d;
+impl<'a, L:Clone> Generator for Dlx<'a, L> {
+    type Item = [L];
+    type Final = ();
+    fn gen<F, R>(&mut self, visit: F)
+        where F: for <'b> FnMut(Cow<'b, Self::Item>) -> R,
+              R: Into<Control<()>>
+    {
+        self.gen_with(&mut Mrv, visit)
+    }
+}
+
+/// The position of a `Dlx` search: for each level, the column being
+/// branched on there and the (input) row being tried for it, or
+/// `None` when no more rows are to cover that column.
+///
+/// Snapshots are written as text, one level per line, so that a long
+/// enumeration can be checkpointed to a file and picked up again by
+/// a later process.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Snapshot {
+    levels: Vec<(usize, Option<usize>)>,
+    phase: Phase,
+}
+
+const SNAPSHOT_HEADER: &str = "dlx-snapshot 1";
+
+impl Snapshot {
+    pub fn levels(&self) -> &[(usize, Option<usize>)] { &self.levels }
+
+    pub fn is_done(&self) -> bool { self.phase == Phase::Done }
+
+    pub fn write_to<W:Write>(&self, w: &mut W) -> io::Result<()> {
+        let phase = match self.phase {
+            Phase::Enter => "enter",
+            Phase::Backtrack => "backtrack",
+            Phase::Done => "done",
+            Phase::Next => panic!("no snapshot in mid-step"),
+        };
+        writeln!(w, "{}", SNAPSHOT_HEADER)?;
+        writeln!(w, "{}", phase)?;
+        for &(col, row) in self.levels.iter() {
+            match row {
+                Some(row) => writeln!(w, "{} {}", col, row)?,
+                None => writeln!(w, "{} -", col)?,
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R:BufRead>(r: R) -> io::Result<Snapshot> {
+        fn bad(msg: &str) -> io::Error {
+            io::Error::new(io::ErrorKind::InvalidData, msg)
+        }
+        let mut lines = r.lines();
+        let mut next_line = || -> io::Result<Option<String>> {
+            match lines.next() {
+                None => Ok(None),
+                Some(l) => l.map(Some),
+            }
+        };
+        match next_line()? {
+            Some(ref l) if l.trim() == SNAPSHOT_HEADER => {}
+            _ => return Err(bad("missing snapshot header")),
+        }
+        let phase = match next_line()? {
+            Some(ref l) if l.trim() == "enter" => Phase::Enter,
+            Some(ref l) if l.trim() == "backtrack" => Phase::Backtrack,
+            Some(ref l) if l.trim() == "done" => Phase::Done,
+            _ => return Err(bad("missing snapshot phase")),
+        };
+        let mut levels = vec![];
+        while let Some(l) = next_line()? {
+            let mut words = l.split_whitespace();
+            let col = match words.next().and_then(|w| w.parse().ok()) {
+                Some(col) => col,
+                None if l.trim().is_empty() => continue,
+                None => return Err(bad("bad column in snapshot")),
+            };
+            let row = match words.next() {
+                Some("-") => None,
+                Some(w) => match w.parse() {
+                    Ok(row) => Some(row),
+                    Err(_) => return Err(bad("bad row in snapshot")),
+                },
+                None => return Err(bad("missing row in snapshot")),
+            };
+            levels.push((col, row));
+        }
+        Ok(Snapshot { levels, phase })
+    }
+
+    pub fn save<P:AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut f = File::create(path)?;
+        self.write_to(&mut f)
+    }
+
+    pub fn load<P:AsRef<Path>>(path: P) -> io::Result<Snapshot> {
+        let f = File::open(path)?;
+        Snapshot::read_from(BufReader::new(f))
+    }
+}
+
+/// Builds a `dlx_matrix` for `input` and visits each of its exact
+/// covers, given as the labels of the rows making up the cover.
+pub fn solve<L, M, F, R>(input: &M, visit: F)
+    where L: Clone,
+          M: exact_cover::BitMatrix+exact_cover::ColLabelled<L>+exact_cover::RowLabelled<L>,
+          F: for <'b> FnMut(Cow<'b, [L]>) -> R,
+          R: Into<Control<()>>
+{
+    let mut m = dlx_matrix::new(input);
+    Dlx::new(&mut m).gen(visit);
+}
+
+/// Runs a `Dlx` search on several threads.
+///
+/// The search tree is first walked down to a fixed depth; each node
+/// there becomes a job, which a worker thread solves on its own copy
+/// of the matrix by resuming from that node's `Snapshot`. Solutions
+/// are handed back to the calling thread, so the visitor need not be
+/// thread-safe, but they arrive in no particular order.
+pub mod parallel {
+    use std::borrow::Cow;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc::sync_channel;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    use generators::n_tuples::Control;
+    use super::{ColChooser, Dlx, SearchStats, Snapshot, Step, dlx_matrix};
+
+    /// How many nodes a worker searches between checks for whether
+    /// the visitor has asked to stop.
+    const NODES_PER_CHECK: u64 = 1 << 12;
+
+    /// Walks the search for `m` down to level `depth`, returning one
+    /// snapshot per node there, plus the solutions found above it.
+    fn split<L, C>(m: &mut dlx_matrix<L>, depth: usize, chooser: &mut C)
+                   -> (Vec<Snapshot>, Vec<Vec<L>>, SearchStats)
+        where L: Clone, C: ColChooser<L>
+    {
+        let mut dlx = Dlx::new(m);
+        dlx.split_depth = Some(depth);
+        let mut jobs = vec![];
+        let mut shallow = vec![];
         loop {
-            let obj = self.m.data[*cursor];
-            print!("{:s} ", self.m.cols[*cursor.C(self.m)].N);
-            cursor = obj.R;
-            if cursor == d {
-                break;
+            match dlx.step(chooser, u64::MAX) {
+                Step::Solution => shallow.push(dlx.solution()),
+                Step::Paused => {
+                    jobs.push(dlx.snapshot());
+                    dlx.phase = super::Phase::Backtrack;
+                }
+                Step::Done => break,
+            }
+        }
+        (jobs, shallow, dlx.stats.clone())
+    }
+
+    /// Visits every solution of `m`, splitting the search at level
+    /// `depth` and solving the parts on `threads` worker threads.
+    /// Each worker branches on the columns picked by its own chooser,
+    /// made by `make_chooser`. Returns the combined search statistics.
+    pub fn gen<L, C, MC, F, R>(m: &dlx_matrix<L>, depth: usize, threads: usize,
+                               make_chooser: MC, mut visit: F) -> SearchStats
+        where L: Clone + Send + 'static,
+              C: ColChooser<L>,
+              MC: Fn() -> C + Send + Sync + 'static,
+              F: for <'b> FnMut(Cow<'b, [L]>) -> R,
+              R: Into<Control<()>>
+    {
+        assert!(threads > 0);
+        let mut m = m.clone();
+        let (jobs, shallow, mut stats) = split(&mut m, depth, &mut make_chooser());
+
+        for labels in shallow.iter() {
+            match visit(Cow::Borrowed(&labels[..])).into() {
+                Control::Break(()) => return stats,
+                Control::Yield => {}
+            }
+        }
+
+        let jobs = Arc::new(Mutex::new(jobs));
+        let stop = Arc::new(AtomicBool::new(false));
+        let make_chooser = Arc::new(make_chooser);
+        let (tx, rx) = sync_channel::<Vec<L>>(1024);
+        let workers: Vec<_> = (0..threads).map(|_| {
+            let mut m = m.clone();
+            let jobs = jobs.clone();
+            let stop = stop.clone();
+            let make_chooser = make_chooser.clone();
+            let tx = tx.clone();
+            thread::spawn(move || {
+                let mut chooser = make_chooser();
+                let mut dlx = Dlx::new(&mut m);
+                let mut stats = SearchStats::default();
+                'jobs: loop {
+                    let job = match jobs.lock().unwrap().pop() {
+                        Some(job) => job,
+                        None => break,
+                    };
+                    dlx.resume_subtree(&job).expect("jobs come from this matrix");
+                    dlx.stats = SearchStats::default();
+                    while !stop.load(Ordering::Relaxed) {
+                        match dlx.step(&mut chooser, NODES_PER_CHECK) {
+                            Step::Solution => {
+                                if tx.send(dlx.solution()).is_err() {
+                                    break 'jobs;
+                                }
+                            }
+                            Step::Paused => {}
+                            Step::Done => break,
+                        }
+                    }
+                    stats.merge(&dlx.stats);
+                }
+                stats
+            })
+        }).collect();
+        drop(tx);
+
+        for labels in rx.iter() {
+            match visit(Cow::Borrowed(&labels[..])).into() {
+                Control::Break(()) => {
+                    stop.store(true, Ordering::Relaxed);
+                    break;
+                }
+                Control::Yield => {}
             }
         }
+        drop(rx);
+
+        for w in workers {
+            stats.merge(&w.join().unwrap());
+        }
+        stats
     }
+
+    /// Like `gen`, but only counts the solutions.
+    pub fn count<L, C, MC>(m: &dlx_matrix<L>, depth: usize, threads: usize,
+                           make_chooser: MC) -> SearchStats
+        where L: Clone + Send + 'static,
+              C: ColChooser<L>,
+              MC: Fn() -> C + Send + Sync + 'static
+    {
+        assert!(threads > 0);
+        let mut m = m.clone();
+        let (jobs, _, mut stats) = split(&mut m, depth, &mut make_chooser());
+
+        let jobs = Arc::new(Mutex::new(jobs));
+        let make_chooser = Arc::new(make_chooser);
+        let workers: Vec<_> = (0..threads).map(|_| {
+            let mut m = m.clone();
+            let jobs = jobs.clone();
+            let make_chooser = make_chooser.clone();
+            thread::spawn(move || {
+                let mut chooser = make_chooser();
+                let mut dlx = Dlx::new(&mut m);
+                let mut stats = SearchStats::default();
+                loop {
+                    let job = match jobs.lock().unwrap().pop() {
+                        Some(job) => job,
+                        None => break,
+                    };
+                    dlx.resume_subtree(&job).expect("jobs come from this matrix");
+                    dlx.stats = SearchStats::default();
+                    while dlx.step(&mut chooser, u64::MAX) != Step::Done { }
+                    stats.merge(&dlx.stats);
+                }
+                stats
+            })
+        }).collect();
+
+        for w in workers {
+            stats.merge(&w.join().unwrap());
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+fn sorted_solns<L:Clone+Ord>(solns: Vec<Vec<L>>) -> Vec<Vec<L>> {
+    let mut solns: Vec<Vec<L>> = solns.into_iter()
+        .map(|mut s| { s.sort(); s })
+        .collect();
+    solns.sort();
+    solns
+}
+
+#[test]
+fn dlx_instance_1() {
+    let input = exact_cover::simple_exact_cover_instance_1();
+    let mut results: Vec<Vec<&str>> = vec![];
+    solve(&input, |s| results.push(s.into_owned()));
+    assert_eq!(sorted_solns(results), [["1", "4", "5"]]);
 }
 
-fn main() {
+#[test]
+fn dlx_instance_2() {
+    let input = exact_cover::simple_exact_cover_instance_2();
+    let mut count = 0;
+    solve(&input, |_| count += 1);
+    assert_eq!(count, 0);
+}
+
+#[test]
+fn dlx_col_choosers() {
     let input = exact_cover::simple_exact_cover_instance_1();
-    println!("Hello world input: {}", input);
     let mut m = dlx_matrix::new(&input);
-    // println!("yields {:?}", m);
-    let mut dlx = Dlx { m: &mut m, soln: vec![], count_updates: 0 };
+    let mut results: Vec<Vec<&str>> = vec![];
+    Dlx::new(&mut m).gen_with(&mut FirstCol, |s| results.push(s.into_owned()));
+    assert_eq!(sorted_solns(results), [["1", "4", "5"]]);
+
+    let mrv = Dlx::new(&mut m).count();
+    for seed in 0..10 {
+        let stats = Dlx::new(&mut m).count_with(&mut MrvRandomTies::new(seed));
+        assert_eq!(stats.solutions, 1);
+        assert_eq!(stats.max_depth, mrv.max_depth);
+    }
+
+    // A user-defined chooser, preferring column "g" whenever it is
+    // active, and remembering which levels it was consulted at.
+    let mut levels = vec![];
+    let stats = {
+        let mut prefer_g = |m: &dlx_matrix<&str>, level: usize| {
+            levels.push(level);
+            let cols: Vec<usize> = m.active_cols().collect();
+            *cols.iter().find(|&&c| *m.col_label(c) == "g").unwrap_or(&cols[0])
+        };
+        Dlx::new(&mut m).count_with(&mut prefer_g)
+    };
+    assert_eq!(stats.solutions, 1);
+    assert_eq!(levels[0], 0);
+    assert_eq!(levels.len() as u64, stats.total_nodes() - stats.solutions);
+}
+
+#[test]
+fn dlx_builtin_choosers() {
+    // "x" is in every row, while "y" and "z" tie for the fewest.
+    let input = exact_cover::Matrix::new(
+        vec!["x", "y", "z"],
+        vec!["1", "2", "3"],
+        vec![1usize, 1, 0,
+             1, 0, 1,
+             1, 0, 0, ]);
+    let m = dlx_matrix::new(&input);
+    let order: Vec<&str> = m.active_cols().map(|c| *m.col_label(c)).collect();
+    let first_tie = *order.iter().find(|&&l| l != "x").unwrap();
+    assert_eq!(*m.col_label(FirstCol.choose(&m, 0)), order[0]);
+    assert_eq!(*m.col_label(Mrv.choose(&m, 0)), first_tie);
+
+    let mut seen = vec![];
+    for seed in 0..20 {
+        let c = MrvRandomTies::new(seed).choose(&m, 0);
+        seen.push(*m.col_label(c));
+    }
+    seen.sort();
+    seen.dedup();
+    assert_eq!(seen, ["y", "z"]);
+}
+
+#[test]
+fn dlx_sparse_input() {
+    // Row i covers columns i and i+1 (mod 2n) of a wide matrix, so
+    // the exact covers are the two perfect matchings of a cycle.
+    let n = 500;
+    let cols: Vec<String> = (0..2*n).map(|c| format!("c{}", c)).collect();
+    let mut input = exact_cover::SparseMatrix::new(cols);
+    for i in 0..2*n {
+        input.push_row(format!("r{}", i), &[i, (i + 1) % (2*n)]);
+    }
+    let mut m = dlx_matrix::new(&input);
+    assert_eq!(m.data.len(), input.num_ones());
+    let mut results: Vec<Vec<String>> = vec![];
+    Dlx::new(&mut m).gen(|s| results.push(s.into_owned()));
+    assert_eq!(results.len(), 2);
+    for s in results.iter() { assert_eq!(s.len(), n); }
+}
+
+#[test]
+fn dlx_secondary_cols() {
+    // `s` is secondary, so rows 1 and 2 cannot both be chosen, but
+    // neither is required.
+    let input = exact_cover::Matrix::new(
+        vec!["a", "c", "s"],
+        vec!["1", "2", "3", "4"],
+        vec![1usize, 0, 1,
+             0, 1, 1,
+             1, 0, 0,
+             0, 1, 0, ]);
+    let mut m = dlx_matrix::with_secondary(&input, |c| c == 2);
+    let mut results: Vec<Vec<&str>> = vec![];
+    Dlx::new(&mut m).gen(|s| results.push(s.into_owned()));
+    assert_eq!(sorted_solns(results), [["1", "4"], ["2", "3"], ["3", "4"]]);
 
-    let trivial_col_choice = |m:&dlx_matrix<&'static str>| {
-        match m.root.R {
-            Ccr(cf) => cf,
-            Rootcr  => fail!("should not choose col on empty matrix")
+    // Treated as primary, `s` must be covered exactly once.
+    let mut results: Vec<Vec<&str>> = vec![];
+    solve(&input, |s| results.push(s.into_owned()));
+    assert_eq!(sorted_solns(results), [["1", "4"], ["2", "3"]]);
+}
+
+#[test]
+fn dlx_colored_secondary_cols() {
+    // The XCC example from TAOCP 7.2.2.1: primary items p, q, r and
+    // secondary items x, y, with options
+    //   1: p q x y:A   2: p r x:A y   3: p x:B   4: q x:A   5: r y:B
+    let input = exact_cover::Matrix::new(
+        vec!["p", "q", "r", "x", "y"],
+        vec!["1", "2", "3", "4", "5"],
+        vec![1usize, 1, 0, 1, 1,
+             1, 0, 1, 1, 1,
+             1, 0, 0, 1, 0,
+             0, 1, 0, 1, 0,
+             0, 0, 1, 0, 1, ]);
+    const A: usize = 0;
+    const B: usize = 1;
+    let mut m = dlx_matrix::with_colors(&input, |c| c >= 3, |c, r| {
+        match (c, r) {
+            (4, 0) | (3, 1) | (3, 3) => Some(A),
+            (3, 2) | (4, 4) => Some(B),
+            _ => None,
         }
+    });
+    let mut results: Vec<Vec<&str>> = vec![];
+    Dlx::new(&mut m).gen(|s| results.push(s.into_owned()));
+    assert_eq!(sorted_solns(results), [["2", "4"]]);
+}
+
+#[test]
+fn dlx_multiplicities() {
+    // Column `a` must be covered by two or three rows; `b` exactly once.
+    let input = exact_cover::Matrix::new(
+        vec!["a", "b"],
+        vec!["1", "2", "3", "4"],
+        vec![1usize, 0,
+             1, 0,
+             1, 1,
+             0, 1, ]);
+    let mut m = dlx_matrix::new(&input);
+    m.set_bounds(0, 2, 3);
+    let mut results: Vec<Vec<&str>> = vec![];
+    Dlx::new(&mut m).gen(|s| results.push(s.into_owned()));
+    assert_eq!(sorted_solns(results),
+               vec![vec!["1", "2", "3"], vec!["1", "2", "4"],
+                    vec!["1", "3"], vec!["2", "3"]]);
+
+    // With `a` optional, it may also be left uncovered.
+    m.set_bounds(0, 0, 1);
+    let mut results: Vec<Vec<&str>> = vec![];
+    Dlx::new(&mut m).gen(|s| results.push(s.into_owned()));
+    assert_eq!(sorted_solns(results),
+               vec![vec!["1", "4"], vec!["2", "4"], vec!["3"], vec!["4"]]);
+}
+
+#[test]
+fn dlx_count() {
+    let input = exact_cover::Matrix::new(
+        vec!["a", "b"],
+        vec!["1", "2", "3", "4"],
+        vec![1usize, 0,
+             1, 0,
+             0, 1,
+             0, 1, ]);
+    let mut m = dlx_matrix::new(&input);
+    let stats = Dlx::new(&mut m).count();
+    assert_eq!(stats.solutions, 4);
+    assert_eq!(stats.nodes, [1, 2, 4]);
+    assert_eq!(stats.max_depth, 2);
+    assert_eq!(stats.total_nodes(), 7);
+
+    let input = exact_cover::simple_exact_cover_instance_1();
+    let mut m = dlx_matrix::new(&input);
+    let mut dlx = Dlx::new(&mut m);
+    let stats = dlx.count();
+    assert_eq!(stats.solutions, 1);
+    assert_eq!(stats.max_depth, 3);
+    assert_eq!(stats.nodes.len(), 4);
+    assert!(stats.total_updates() > 0);
+    assert_eq!(dlx.count(), stats);
+}
+
+#[test]
+fn dlx_snapshot_resume() {
+    let input = exact_cover::Matrix::new(
+        vec!["a", "b", "c"],
+        vec!["1", "2", "3", "4", "5"],
+        vec![1usize, 0, 0,
+             1, 0, 1,
+             1, 1, 0,
+             0, 1, 0,
+             0, 0, 1, ]);
+    let fresh = || {
+        let mut m = dlx_matrix::new(&input);
+        m.set_bounds(0, 1, 2);
+        m
     };
+    let mut m = fresh();
+    let mut full: Vec<Vec<&str>> = vec![];
+    Dlx::new(&mut m).gen(|s| full.push(s.into_owned()));
+    assert!(full.len() > 2);
 
-    let min_branching_col_choice = |m:&dlx_matrix<&'static str>| -> Cf {
-        let mut j = m.root.R;
-        let mut c = j;
-        let mut s = None; // represents +infinity
-        loop {
-            match j {
-                Rootcr => break,
-                Ccr(jd) => {
-                    match (m.cols[*jd].S, s) {
-                        (s2, None)                => { s = Some(s2); c = j; }
-                        (s2, Some(s1)) if s2 < s1 => { s = Some(s2); c = j; }
-                        _ => {} }
-                    j = jd.R(m);
-                }
-            }
+    // Walk the search a node at a time; from every position, a
+    // resumed search must find exactly the solutions still to come.
+    let mut dlx = Dlx::new(&mut m);
+    let mut seen = vec![];
+    loop {
+        let snap = dlx.snapshot();
+        let mut buf = vec![];
+        snap.write_to(&mut buf).unwrap();
+        let snap2 = Snapshot::read_from(&buf[..]).unwrap();
+        assert_eq!(snap, snap2);
+
+        let mut m2 = fresh();
+        let mut dlx2 = Dlx::new(&mut m2);
+        dlx2.resume(&snap2).unwrap();
+        let mut all = seen.clone();
+        while dlx2.step(&mut Mrv, u64::MAX) == Step::Solution {
+            all.push(dlx2.solution());
         }
+        assert_eq!(all, full);
 
-        match c {
-            Ccr(cf) => cf,
-            Rootcr  => fail!("should not choose col on empty matrix")
+        match dlx.step(&mut Mrv, 1) {
+            Step::Solution => seen.push(dlx.solution()),
+            Step::Paused => {}
+            Step::Done => break,
+        }
+    }
+    assert_eq!(seen, full);
+    assert!(dlx.snapshot().is_done());
+}
+
+#[test]
+fn dlx_snapshot_file() {
+    let input = exact_cover::simple_exact_cover_instance_1();
+    let mut m = dlx_matrix::new(&input);
+    let mut dlx = Dlx::new(&mut m);
+    assert_eq!(dlx.step(&mut Mrv, 2), Step::Paused);
+    let snap = dlx.snapshot();
+    assert_eq!(snap.levels().len(), 2);
+
+    let path = ::std::env::temp_dir().join("taocp-dlx-snapshot-test.txt");
+    snap.save(&path).unwrap();
+    let loaded = Snapshot::load(&path).unwrap();
+    ::std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded, snap);
+
+    assert!(Snapshot::read_from(&b"dlx-snapshot 1\nenter\n0 x\n"[..]).is_err());
+    assert!(Snapshot::read_from(&b"not a snapshot\n"[..]).is_err());
+
+    // A snapshot of another matrix is refused, and leaves the search
+    // at its start.
+    let other = exact_cover::simple_exact_cover_instance_2();
+    let mut m2 = dlx_matrix::new(&other);
+    let mut dlx2 = Dlx::new(&mut m2);
+    assert!(dlx2.resume(&loaded).is_err());
+    assert!(dlx2.resume_subtree(&loaded).is_err());
+    assert_eq!(dlx2.count().solutions, 0);
+    let mut m3 = dlx_matrix::new(&input);
+    let mut dlx3 = Dlx::new(&mut m3);
+    let stale = Snapshot::read_from(&b"dlx-snapshot 1\nenter\n0 0\n"[..]).unwrap();
+    assert!(dlx3.resume(&stale).is_err());
+    assert_eq!(dlx3.count().solutions, 1);
+}
+
+#[test]
+fn dlx_parallel() {
+    // Partitions of six items into singletons and pairs, of which
+    // there are as many as involutions of six things: 76.
+    let n = 6;
+    let mut elems = vec![];
+    let mut rows = vec![];
+    for i in 0..n {
+        for j in i..n {
+            rows.push(i * n + j);
+            for c in 0..n {
+                elems.push(if c == i || c == j { 1usize } else { 0 });
+            }
         }
+    }
+    let input = exact_cover::Matrix::new((0..n).collect(), rows, elems);
+    let mut m = dlx_matrix::new(&input);
+    let serial = Dlx::new(&mut m).count();
+    assert_eq!(serial.solutions, 76);
+    assert!(serial.total_updates() > 0);
 
+    for &depth in [0, 1, 2, 4].iter() {
+        let stats = parallel::count(&m, depth, 3, || Mrv);
+        assert_eq!(stats, serial);
+
+        let mut results: Vec<Vec<usize>> = vec![];
+        let stats = parallel::gen(&m, depth, 3, || Mrv, |s| results.push(s.into_owned()));
+        assert_eq!(stats.solutions, 76);
+        let mut expected: Vec<Vec<usize>> = vec![];
+        Dlx::new(&mut m).gen(|s| expected.push(s.into_owned()));
+        assert_eq!(sorted_solns(results), sorted_solns(expected));
+    }
+
+    let mut count = 0;
+    parallel::gen(&m, 2, 3, || Mrv, |_| { count += 1; if count == 5 { Control::Break(()) } else { Control::Yield } });
+    assert_eq!(count, 5);
+}
+
+#[test]
+fn dlx_zdd() {
+    // The 76 partitions of six items into singletons and pairs, as
+    // in `dlx_parallel`.
+    let n = 6;
+    let mut elems = vec![];
+    let mut rows = vec![];
+    for i in 0..n {
+        for j in i..n {
+            rows.push(i * n + j);
+            for c in 0..n {
+                elems.push(if c == i || c == j { 1usize } else { 0 });
+            }
+        }
+    }
+    let input = exact_cover::Matrix::new((0..n).collect(), rows, elems);
+    let mut m = dlx_matrix::new(&input);
+    let mut z = {
+        let mut dlx = Dlx::new(&mut m);
+        let z = dlx.zdd(&mut Mrv);
+        assert_eq!(dlx.stats().solutions, 76);
+        z
     };
+    assert_eq!(z.count(), 76);
+
+    let mut expected: Vec<Vec<usize>> = vec![];
+    Dlx::new(&mut m).gen(|s| expected.push(s.into_owned()));
+    let mut results: Vec<Vec<usize>> = vec![];
+    z.gen(|s| results.push(s.iter().map(|&r| *m.row_label(r)).collect()));
+    assert_eq!(sorted_solns(results), sorted_solns(expected.clone()));
+
+    // Solutions containing the pair {0, 1} (row 1): the involutions
+    // of the other four items, of which there are 10.
+    assert_eq!(*m.row_label(1), 1);
+    assert_eq!(z.count_containing(1), 10);
+    for r in 0..m.rows.len() {
+        let want = expected.iter().filter(|s| s.contains(m.row_label(r))).count();
+        assert_eq!(z.count_containing(r), want as u128);
+    }
+
+    // Memoizing on the uncovered columns shares subproblems, so the
+    // diagram is smaller than the search tree.
+    assert!(z.size() < Dlx::new(&mut m).count().total_nodes() as usize);
 
-    // dlx.search(0, &trivial_col_choice);
-    dlx.search(0, &min_branching_col_choice);
-    println!("dlx.count_updates: {}", dlx.count_updates);
+    // Sampling is uniform: each of the 76 should turn up about
+    // 7600 / 76 = 100 times.
+    let mut rng = XorShift::new(2);
+    let mut hits: HashMap<Vec<usize>, usize> = HashMap::new();
+    for _ in 0..7600 {
+        let mut s = z.sample(|k| rng.below(k as u64) as u128).unwrap();
+        s.sort();
+        *hits.entry(s).or_insert(0) += 1;
+    }
+    assert_eq!(hits.len(), 76);
+    assert!(hits.values().all(|&h| 50 < h && h < 150));
+
+    // Secondary columns: the queens of a 6-by-6 board.
+    let inst = ::puzzles::queens::Queens::new(6).instance();
+    let mut m = inst.dlx();
+    assert_eq!(Dlx::new(&mut m).zdd(&mut Mrv).count(), 4);
+}
+
+#[test]
+fn dlx_estimate() {
+    // Every node at a given level has the same degree here, so each
+    // probe is exact: 1 + 3 + 9 + 27 + 81 nodes, and 81 solutions.
+    let mut elems = vec![];
+    let mut rows = vec![];
+    for r in 0..12 {
+        rows.push(r);
+        for c in 0..4 {
+            elems.push(if r / 3 == c { 1usize } else { 0 });
+        }
+    }
+    let input = exact_cover::Matrix::new(vec![0, 1, 2, 3], rows, elems);
+    let mut m = dlx_matrix::new(&input);
+    let est = Dlx::new(&mut m).estimate(&mut Mrv, 10, 1);
+    assert_eq!(est.nodes, 121.0);
+    assert_eq!(est.nodes_variance, 0.0);
+    assert_eq!(est.solutions, 81.0);
+    assert_eq!(est.solutions_variance, 0.0);
+
+    // An irregular tree: the estimates are only right on average.
+    let input = exact_cover::Matrix::new(
+        vec!["a", "b", "c", "d"],
+        vec!["1", "2", "3", "4", "5", "6", "7"],
+        vec![1usize, 1, 0, 0,
+             1, 0, 0, 0,
+             0, 1, 0, 0,
+             0, 0, 1, 1,
+             0, 0, 1, 0,
+             0, 0, 0, 1,
+             0, 1, 1, 0, ]);
+    let mut m = dlx_matrix::new(&input);
+    let exact = Dlx::new(&mut m).count();
+    let est = Dlx::new(&mut m).estimate(&mut Mrv, 20000, 7);
+    assert_eq!(est.probes, 20000);
+    assert!((est.solutions - exact.solutions as f64).abs() < 0.1 * exact.solutions as f64);
+    assert!((est.nodes - exact.total_nodes() as f64).abs() < 0.1 * exact.total_nodes() as f64);
+    assert!(est.solutions_variance > 0.0);
+    assert_eq!(est, Dlx::new(&mut m).estimate(&mut Mrv, 20000, 7));
+    assert_eq!(Dlx::new(&mut m).count(), exact);
+}
+
+#[test]
+fn dlx_break_restores_matrix() {
+    // Two disjoint copies of a two-row column, so there are four covers.
+    let input = exact_cover::Matrix::new(
+        vec!["a", "b"],
+        vec!["1", "2", "3", "4"],
+        vec![1usize, 0,
+             1, 0,
+             0, 1,
+             0, 1, ]);
+    let mut m = dlx_matrix::new(&input);
+    let mut count = 0;
+    Dlx::new(&mut m).gen(|_| { count += 1; Control::Break(()) });
+    assert_eq!(count, 1);
+    let mut count = 0;
+    Dlx::new(&mut m).gen(|_| count += 1);
+    assert_eq!(count, 4);
 }
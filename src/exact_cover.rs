@@ -3,66 +3,67 @@
 use std::cmp::min;
 use std::fmt;
 
-trait TakeIterOn<T, A> {
-    fn go<I:Iterator<T>>(&self, iter: &mut I) -> A;
+pub trait TakeIterOn<T, A> {
+    fn go<I:Iterator<Item=T>>(&self, iter: &mut I) -> A;
 }
 
-struct Accumulate;
+pub struct Accumulate;
 impl<T:Clone> TakeIterOn<T, Vec<T>> for Accumulate {
-    fn go<I:Iterator<T>>(&self, iter: &mut I) -> Vec<T> {
+    fn go<I:Iterator<Item=T>>(&self, iter: &mut I) -> Vec<T> {
         iter.collect()
     }
 }
 
-pub trait BitMatrix {
-    fn num_cols(&self) -> uint;
-    fn num_rows(&self) -> uint;
-    fn at(&self, col: uint, row: uint) -> bool;
-    fn without_row(&self, row: uint) -> Self;
-    fn without_col(&self, col: uint) -> Self;
+pub trait BitMatrix: Sized {
+    fn num_cols(&self) -> usize;
+    fn num_rows(&self) -> usize;
+    fn at(&self, col: usize, row: usize) -> bool;
+    fn without_row(&self, row: usize) -> Self;
+    fn without_col(&self, col: usize) -> Self;
 
     fn is_covered(&self) -> bool {
         self.num_cols() == 0
     }
-    fn rows_on<'a, A, K: TakeIterOn<uint, A>>(&'a self, col: uint, k: &K) -> A {
+    fn rows_on<A, K: TakeIterOn<usize, A>>(&self, col: usize, k: &K) -> A {
         // println!("col: {}, num_cols: {}", col, self.num_cols());
         assert!(col < self.num_cols());
-        let mut i = GenericRowIter{ mat: self, col: col, cursor: 0 };
+        let mut i = GenericRowIter{ mat: self, col, cursor: 0 };
         k.go(&mut i)
     }
-    fn cols_on<'a, A, K: TakeIterOn<uint, A>>(&'a self, row: uint, k: &K) -> A {
+    fn cols_on<A, K: TakeIterOn<usize, A>>(&self, row: usize, k: &K) -> A {
         assert!(row < self.num_rows());
-        let mut i = GenericColIter{ mat: self, row: row, cursor: 0 };
+        let mut i = GenericColIter{ mat: self, row, cursor: 0 };
         k.go(&mut i)
     }
 
 
-    fn col(&self, col: uint) -> Vec<bool> {
+    fn col(&self, col: usize) -> Vec<bool> {
         let mut accum = vec![];
         let r = self.num_rows();
-        for row in range(0, r) {
+        for row in 0..r {
             accum.push(self.at(col, row));
         }
         accum
     }
-    fn row(&self, row: uint) -> Vec<bool> {
+    fn row(&self, row: usize) -> Vec<bool> {
         let mut accum = vec![];
         let c = self.num_cols();
-        for col in range(0, c) {
+        for col in 0..c {
             accum.push(self.at(col, row));
         }
         accum
-            }
-        }
+    }
+}
 
-pub trait ColLabelled<L> { fn col_label<'a>(&'a self, col: uint) -> &'a L; }
-pub trait RowLabelled<L> { fn row_label<'a>(&'a self, row: uint) -> &'a L; }
+pub trait ColLabelled<L> { fn col_label(&self, col: usize) -> &L; }
+pub trait RowLabelled<L> { fn row_label(&self, row: usize) -> &L; }
 
-struct GenericColIter<'a, M> { mat: &'a M, row: uint, cursor: uint }
-struct GenericRowIter<'a, M> { mat: &'a M, col: uint, cursor: uint }
+struct GenericColIter<'a, M> { mat: &'a M, row: usize, cursor: usize }
+struct GenericRowIter<'a, M> { mat: &'a M, col: usize, cursor: usize }
 
-impl<'a, M:BitMatrix> Iterator<uint> for GenericColIter<'a, M> {
-    fn next(&mut self) -> Option<uint> {
+impl<'a, M:BitMatrix> Iterator for GenericColIter<'a, M> {
+    type Item = usize;
+    fn next(&mut self) -> Option<usize> {
         assert!(self.row < self.mat.num_rows());
         let num_cols = self.mat.num_cols();
         loop {
@@ -78,8 +79,9 @@ impl<'a, M:BitMatrix> Iterator<uint> for GenericColIter<'a, M> {
     }
 }
 
-impl<'a, M:BitMatrix> Iterator<uint> for GenericRowIter<'a, M> {
-    fn next(&mut self) -> Option<uint> {
+impl<'a, M:BitMatrix> Iterator for GenericRowIter<'a, M> {
+    type Item = usize;
+    fn next(&mut self) -> Option<usize> {
         assert!(self.col < self.mat.num_cols());
         let num_rows = self.mat.num_rows();
         loop {
@@ -96,58 +98,45 @@ impl<'a, M:BitMatrix> Iterator<uint> for GenericRowIter<'a, M> {
 }
 
 trait MutBitMatrix : BitMatrix {
-    fn put(&mut self, col: uint, row: uint, val: bool);
+    fn put(&mut self, col: usize, row: usize, val: bool);
 }
 
-#[deriving(Clone)]
+#[derive(Clone)]
 pub struct Matrix<L, T> {
-    col_indent: String,
     cols: Vec<L>,
     rows: Vec<L>,
     elems: Vec<T> }
 
-impl<L:fmt::String, T> RowLabelled<L> for Matrix<L, T> {
-    fn row_label<'a>(&'a self, row: uint) -> &'a L { self.rows.get(row) }
+impl<L, T> RowLabelled<L> for Matrix<L, T> {
+    fn row_label(&self, row: usize) -> &L { &self.rows[row] }
 }
 
-impl<L:fmt::String, T> ColLabelled<L> for Matrix<L, T> {
-    fn col_label<'a>(&'a self, col: uint) -> &'a L { self.cols.get(col) }
+impl<L, T> ColLabelled<L> for Matrix<L, T> {
+    fn col_label(&self, col: usize) -> &L { &self.cols[col] }
 }
 
-impl<L:fmt::String, T:fmt::Show> fmt::Show for Matrix<L, T> {
+impl<L:fmt::Display+Clone, B:ToBool+Clone> fmt::Display for Matrix<L, B> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let obj = self;
-        let mut line   = 0u;
-        let mut cursor = 0;
-        write!(f.buf, "\n{:s}( ", obj.col_indent);
-        for l in obj.cols.iter() {
-            write!(f.buf, "{:s} ", *l);
-        }
-        let width = obj.cols.len();
-        while cursor < obj.elems.len() {
-            write!(f.buf, "|\n{:s}| ", *obj.rows.get(line));
-            for e in obj.elems.slice(cursor, cursor + width).iter() {
-                write!(f.buf, "{} ", *e);
-            }
-            cursor = cursor + width;
-            line = line + 1;
-        }
-        write!(f.buf, ")");
+        f.write_str(&render::text(self, &[]))
     }
 }
 
 impl<L, T> Matrix<L, T> {
-    pub fn at<'a>(&'a self, col: uint, row: uint) -> &'a T {
+    pub fn new(cols: Vec<L>, rows: Vec<L>, elems: Vec<T>) -> Matrix<L, T> {
+        Matrix { cols, rows, elems }
+    }
+
+    pub fn at(&self, col: usize, row: usize) -> &T {
         let width = self.cols.len();
         let rw = row * width;
         let len = self.elems.len();
         assert!(rw + col < len);
-        self.elems.get((row * width) + col)
+        &self.elems[(row * width) + col]
     }
 
-    pub fn put(&mut self, col: uint, row: uint, t: T) {
+    pub fn put(&mut self, col: usize, row: usize, t: T) {
         let width = self.cols.len();
-        *self.elems.get((row * width) + col) = t;
+        self.elems[(row * width) + col] = t;
     }
 }
 
@@ -156,78 +145,174 @@ trait ToBool {
 }
 
 impl ToBool for bool { fn to_bool(&self) -> bool { *self } }
-impl ToBool for uint { fn to_bool(&self) -> bool { *self != 0u } }
-impl ToBool for int  { fn to_bool(&self) -> bool { *self != 0 } }
+impl ToBool for usize { fn to_bool(&self) -> bool { *self != 0 } }
+impl ToBool for isize { fn to_bool(&self) -> bool { *self != 0 } }
 
 impl<L:Clone, B:ToBool+Clone> BitMatrix for Matrix<L, B> {
-    fn num_cols(&self) -> uint { self.cols.len() }
-    fn num_rows(&self) -> uint { self.elems.len() / self.num_cols() }
-    fn at(&self, col: uint, row: uint) -> bool { self.at(col, row).to_bool() }
-    fn without_row(&self, row: uint) -> Matrix<L, B> {
+    fn num_cols(&self) -> usize { self.cols.len() }
+    fn num_rows(&self) -> usize { self.rows.len() }
+    fn at(&self, col: usize, row: usize) -> bool { self.at(col, row).to_bool() }
+    fn without_row(&self, row: usize) -> Matrix<L, B> {
         assert!(row < self.num_rows());
-        let lft = self.elems.slice_to(row * self.num_cols());
-        let rgt = self.elems.slice_from((row+1) * self.num_cols());
+        let lft = &self.elems[..row * self.num_cols()];
+        let rgt = &self.elems[(row+1) * self.num_cols()..];
         let mut rows = self.rows.clone();
         rows.remove(row);
-        Matrix { rows: rows,
-                 col_indent: self.col_indent.clone(), cols: self.cols.clone(),
-                 elems: Vec::from_slice(lft).append(rgt) }
+        Matrix { rows, cols: self.cols.clone(),
+                 elems: lft.iter().chain(rgt.iter()).cloned().collect() }
     }
-    fn without_col(&self, col: uint) -> Matrix<L, B> {
+    fn without_col(&self, col: usize) -> Matrix<L, B> {
         let mut accum = vec![];
         let mut cursor = 0;
         let mut next_drop = col;
         let len = self.elems.len();
         while cursor < len {
-            accum.push_all(self.elems.slice(cursor, next_drop));
+            accum.extend(self.elems[cursor..next_drop].iter().cloned());
             cursor = next_drop + 1;
             next_drop = min(len, next_drop + self.num_cols());
         }
         let mut cols = self.cols.clone();
         cols.remove(col);
-        Matrix { rows: self.rows.clone(), 
-                 col_indent: self.col_indent.clone(), cols: cols, elems: accum }
+        Matrix { rows: self.rows.clone(), cols, elems: accum }
+    }
+}
+
+/// A bit matrix stored as lists of the ones in each row (and, for
+/// `rows_on`, in each column), for instances where a row has a few
+/// ones among thousands of columns.  `rows_on` and `cols_on` take
+/// time proportional to the ones they report, rather than to the
+/// whole width (or height) of the matrix, as with `Matrix`.
+#[derive(Clone)]
+pub struct SparseMatrix<L> {
+    cols: Vec<L>,
+    rows: Vec<L>,
+    row_ones: Vec<Vec<usize>>,
+    col_ones: Vec<Vec<usize>>,
+}
+
+impl<L> SparseMatrix<L> {
+    /// An empty matrix (no rows yet) with the given column labels.
+    pub fn new(cols: Vec<L>) -> SparseMatrix<L> {
+        let col_ones = cols.iter().map(|_| vec![]).collect();
+        SparseMatrix { cols, rows: vec![], row_ones: vec![], col_ones }
+    }
+
+    /// Adds a row with ones in exactly the columns `ones`, which may
+    /// be given in any order.
+    pub fn push_row(&mut self, label: L, ones: &[usize]) {
+        let mut ones = ones.to_vec();
+        ones.sort();
+        ones.dedup();
+        let row = self.rows.len();
+        for &c in ones.iter() {
+            assert!(c < self.cols.len());
+            self.col_ones[c].push(row);
+        }
+        self.rows.push(label);
+        self.row_ones.push(ones);
+    }
+
+    /// The number of ones in the whole matrix.
+    pub fn num_ones(&self) -> usize {
+        self.row_ones.iter().map(|r| r.len()).sum()
+    }
+}
+
+impl<L> RowLabelled<L> for SparseMatrix<L> {
+    fn row_label(&self, row: usize) -> &L { &self.rows[row] }
+}
+
+impl<L> ColLabelled<L> for SparseMatrix<L> {
+    fn col_label(&self, col: usize) -> &L { &self.cols[col] }
+}
+
+impl<L:fmt::Display+Clone> fmt::Display for SparseMatrix<L> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&render::text(self, &[]))
+    }
+}
+
+impl<L:Clone> BitMatrix for SparseMatrix<L> {
+    fn num_cols(&self) -> usize { self.cols.len() }
+    fn num_rows(&self) -> usize { self.rows.len() }
+    fn at(&self, col: usize, row: usize) -> bool {
+        self.row_ones[row].binary_search(&col).is_ok()
+    }
+    fn without_row(&self, row: usize) -> SparseMatrix<L> {
+        assert!(row < self.num_rows());
+        let mut m = self.clone();
+        m.rows.remove(row);
+        m.row_ones.remove(row);
+        for rows in m.col_ones.iter_mut() {
+            rows.retain(|&r| r != row);
+            for r in rows.iter_mut() { if *r > row { *r -= 1; } }
+        }
+        m
+    }
+    fn without_col(&self, col: usize) -> SparseMatrix<L> {
+        assert!(col < self.num_cols());
+        let mut m = self.clone();
+        m.cols.remove(col);
+        m.col_ones.remove(col);
+        for cols in m.row_ones.iter_mut() {
+            cols.retain(|&c| c != col);
+            for c in cols.iter_mut() { if *c > col { *c -= 1; } }
+        }
+        m
+    }
+    fn rows_on<A, K: TakeIterOn<usize, A>>(&self, col: usize, k: &K) -> A {
+        assert!(col < self.num_cols());
+        k.go(&mut self.col_ones[col].iter().cloned())
+    }
+    fn cols_on<A, K: TakeIterOn<usize, A>>(&self, row: usize, k: &K) -> A {
+        assert!(row < self.num_rows());
+        k.go(&mut self.row_ones[row].iter().cloned())
     }
 }
 
-mod x {
+pub mod x {
+    use std::borrow::Cow;
     use std::fmt;
+    use std::marker::PhantomData;
+    use generators::n_tuples::{Control, Generator};
     use super::BitMatrix;
     use super::RowLabelled;
 
     pub trait Solution<T> {
         fn include(&self, component: &T) -> Self;
     }
-    impl<S:Str> Solution<S> for Vec<String> {
+    impl<S:AsRef<str>> Solution<S> for Vec<String> {
         fn include(&self, component: &S) -> Vec<String> {
             let mut v = self.clone();
-            if !v.iter().any(|s|s.equiv(component)) {
+            if !v.iter().any(|s| &s[..] == component.as_ref()) {
                 // let copy = component.into_owned();
                 // Above does not work because `into_owned` takes self by-value
-                let copy = component.as_slice().into_owned();
+                let copy = component.as_ref().to_owned();
                 v.push(copy);
             }
 
-            return v;
+            v
         }
     }
 
     pub fn find_solutions<
-        L:fmt::String+::std::str::Str, // work-around issue #8075
-        M:Clone+BitMatrix+fmt::Show+RowLabelled<L>, S:Clone+Solution<L>>(
+        L:fmt::Display+AsRef<str>,
+        M:Clone+BitMatrix+fmt::Display+RowLabelled<L>, S:Clone+fmt::Debug+Solution<L>,
+        SC:Fn(&M) -> usize>(
         a: &M,
         partial_soln: &S,
-        select_col: &|&M| -> uint) -> Vec<S> {
+        select_col: &SC) -> Vec<S> {
         recur(0, a, partial_soln, select_col)
     }
 
     pub fn recur<
-        L:fmt::String+::std::str::Str, // work-around issue #8075
-        M:Clone+BitMatrix+fmt::Show+RowLabelled<L>,
-        S:Clone+Solution<L>>(level: uint,
-                             a: &M,
-                             partial_soln: &S,
-                             select_col: &|&M| -> uint) -> Vec<S> {
+        L:fmt::Display+AsRef<str>,
+        M:Clone+BitMatrix+fmt::Display+RowLabelled<L>,
+        S:Clone+fmt::Debug+Solution<L>,
+        SC:Fn(&M) -> usize>(level: usize,
+                            a: &M,
+                            partial_soln: &S,
+                            select_col: &SC) -> Vec<S> {
 
         let mut solns = vec![];
         let indent = "    ".repeat(level);
@@ -239,51 +324,852 @@ mod x {
         let c = (*select_col)(a);
         let rows = a.rows_on(c, &super::Accumulate);
         for &r in rows.iter() {
-            debug!("{}solving mat {} for (c,r) = ({},{:s})", indent, *a, c, *a.row_label(r));
+            debug!("{}solving mat {} for (c,r) = ({},{})", indent, *a, c, *a.row_label(r));
             let partial = partial_soln.include(a.row_label(r));
             let cols = a.cols_on(r, &super::Accumulate);
 
             let mut a_new = a.clone();
-            // println!("{:s}  removing row {:s}", indent, *a_new.row_label(r));
+            // println!("{}  removing row {}", indent, *a_new.row_label(r));
             // a_new = a_new.without_row(r);
             for &j in cols.iter().rev() {
-                debug!("{:s} removing column {:u} due to it being covered by row {:s}", indent, j, *a.row_label(r));
+                debug!("{} removing column {} due to it being covered by row {}", indent, j, *a.row_label(r));
 
                 let rows = a_new.rows_on(j, &super::Accumulate);
                 for &i in rows.iter().rev() {
                     if i == r {
-                        debug!("{:s}  removing row {:s}", indent, *a_new.row_label(i));
+                        debug!("{}  removing row {}", indent, *a_new.row_label(i));
                     } else {
-                        debug!("{:s}  removing row {:s} (as it collides with row {:s} on column {:u})", indent, *a_new.row_label(i), *a.row_label(r), j);
+                        debug!("{}  removing row {} (as it collides with row {} on column {})", indent, *a_new.row_label(i), *a.row_label(r), j);
                     }
                     a_new = a_new.without_row(i);
                 }
 
                 a_new = a_new.without_col(j);
-                debug!("{:s} removing col {:u} yielded {}", indent, j, a_new);
+                debug!("{} removing col {} yielded {}", indent, j, a_new);
 
             }
             let sub = recur(level + 1, &a_new, &partial, select_col);
-            solns.push_all(sub.as_slice());
+            solns.extend(sub);
+        }
+
+        solns
+    }
+
+    /// A view of a matrix with some of its rows and columns hidden.
+    ///
+    /// Rather than building a smaller matrix at each step, as
+    /// `recur` does, `find_solutions_in_place` hides rows (with a
+    /// mask) and columns (by swapping them out of a list of the live
+    /// ones), recording each on a trail so that backtracking can
+    /// restore them.  The underlying matrix is never copied.
+    pub struct Active<'a, M:'a> {
+        m: &'a M,
+        row_live: Vec<bool>,
+        cols: Vec<usize>,     // the live columns are `cols[..num_live]`
+        col_pos: Vec<usize>,  // `cols[col_pos[c]] == c`
+        num_live: usize,
+        trail: Vec<Hidden>,
+    }
+
+    enum Hidden { Row(usize), Col(usize) }
+
+    impl<'a, M:BitMatrix> Active<'a, M> {
+        pub fn new(m: &'a M) -> Active<'a, M> {
+            let n = m.num_cols();
+            Active { m, row_live: vec![true; m.num_rows()],
+                     cols: (0..n).collect(), col_pos: (0..n).collect(),
+                     num_live: n, trail: vec![] }
+        }
+
+        pub fn matrix(&self) -> &'a M { self.m }
+
+        /// The columns not yet hidden, in no particular order.
+        pub fn live_cols(&self) -> &[usize] { &self.cols[..self.num_live] }
+
+        pub fn is_live_row(&self, row: usize) -> bool { self.row_live[row] }
+
+        pub fn is_covered(&self) -> bool { self.num_live == 0 }
+
+        /// The live rows with a one in `col`, in increasing order.
+        pub fn rows_on(&self, col: usize) -> Vec<usize> {
+            let rows: Vec<usize> = self.m.rows_on(col, &super::Accumulate);
+            rows.into_iter().filter(|&r| self.row_live[r]).collect()
+        }
+
+        fn hide_row(&mut self, row: usize) {
+            debug_assert!(self.row_live[row]);
+            self.row_live[row] = false;
+            self.trail.push(Hidden::Row(row));
+        }
+
+        fn hide_col(&mut self, col: usize) {
+            let p = self.col_pos[col];
+            debug_assert!(p < self.num_live);
+            let last = self.num_live - 1;
+            let other = self.cols[last];
+            self.cols.swap(p, last);
+            self.col_pos[other] = p;
+            self.col_pos[col] = last;
+            self.num_live = last;
+            self.trail.push(Hidden::Col(col));
+        }
+
+        fn mark(&self) -> usize { self.trail.len() }
+
+        /// Unhides everything hidden since `mark` was taken, in
+        /// reverse order.  (A hidden column sits just past the live
+        /// ones, so bringing it back only grows `num_live`.)
+        fn undo(&mut self, mark: usize) {
+            while self.trail.len() > mark {
+                match self.trail.pop().unwrap() {
+                    Hidden::Row(r) => self.row_live[r] = true,
+                    Hidden::Col(c) => {
+                        debug_assert_eq!(self.col_pos[c], self.num_live);
+                        self.num_live += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Chooses a live column with the fewest live rows, the first one
+    /// in `live_cols` if there is a tie.
+    pub fn choose_min_rows<M:BitMatrix>(a: &Active<M>) -> usize {
+        let mut best = None;
+        for &c in a.live_cols() {
+            let n = a.rows_on(c).len();
+            match best {
+                Some((_, m)) if m <= n => {}
+                _ => best = Some((c, n)),
+            }
+            if n == 0 { break; }
+        }
+        best.expect("no live columns").0
+    }
+
+    /// Finds the same solutions as `find_solutions`, in the same
+    /// order, but works in place on `a` instead of cloning it at
+    /// every branch; see `Active`.
+    pub fn find_solutions_in_place<
+        L:Clone+fmt::Display+AsRef<str>,
+        M:BitMatrix+RowLabelled<L>,
+        S:Clone+fmt::Debug+Solution<L>,
+        SC:Fn(&Active<M>) -> usize>(
+        a: &M,
+        partial_soln: &S,
+        select_col: &SC) -> Vec<S> {
+        let mut solns = vec![];
+        solutions(a, select_col).gen(|labels: Cow<[L]>| {
+            let soln = labels.iter().fold(partial_soln.clone(), |s, l| s.include(l));
+            debug!("soln: {:?}", soln);
+            solns.push(soln);
+        });
+        solns
+    }
+
+    /// The exact covers of `a`, as a `Generator` that visits the
+    /// labels of each cover's rows as soon as it is found (rather
+    /// than collecting them all, as `find_solutions` does), branching
+    /// on the columns that `select_col` picks.
+    pub fn solutions<'a, L, M, SC>(a: &'a M, select_col: SC) -> Solutions<'a, L, M, SC>
+        where M: BitMatrix+RowLabelled<L>, SC: Fn(&Active<M>) -> usize
+    {
+        Solutions { a, select_col, marker: PhantomData }
+    }
+
+    pub struct Solutions<'a, L, M:'a, SC> {
+        a: &'a M,
+        select_col: SC,
+        marker: PhantomData<fn() -> L>,
+    }
+
+    impl<'a, L, M, SC> Generator for Solutions<'a, L, M, SC>
+        where L: Clone, M: BitMatrix+RowLabelled<L>, SC: Fn(&Active<M>) -> usize
+    {
+        type Item = [L];
+        type Final = ();
+        fn gen<F, R>(&mut self, mut visit: F)
+            where F: for <'b> FnMut(Cow<'b, [L]>) -> R,
+                  R: Into<Control<()>>
+        {
+            let mut active = Active::new(self.a);
+            let mut rows = vec![];
+            visit_in_place(&mut active, &mut rows, &self.select_col, &mut visit);
+        }
+    }
+
+    /// Extends the partial cover `rows` in every way that covers the
+    /// rest of `a`, returning true if `visit` asked to stop.  Either
+    /// way, `a` and `rows` are left as they were found.
+    fn visit_in_place<L, M, SC, F, R>(a: &mut Active<M>,
+                                      rows: &mut Vec<usize>,
+                                      select_col: &SC,
+                                      visit: &mut F) -> bool
+        where L: Clone, M: BitMatrix+RowLabelled<L>, SC: Fn(&Active<M>) -> usize,
+              F: for <'b> FnMut(Cow<'b, [L]>) -> R, R: Into<Control<()>>
+    {
+        let m = a.matrix();
+        if a.is_covered() { // problem is solved,
+            let labels: Vec<L> = rows.iter().map(|&r| m.row_label(r).clone()).collect();
+            return match visit(Cow::Owned(labels)).into() {
+                Control::Break(()) => true,
+                Control::Yield => false,
+            };
+        }
+        let c = (*select_col)(a);
+        for r in a.rows_on(c) {
+            let mark = a.mark();
+            // Every column of a live row is live: hiding a column
+            // hides all the rows that have a one in it.
+            let cols: Vec<usize> = m.cols_on(r, &super::Accumulate);
+            for &j in cols.iter() {
+                for i in a.rows_on(j) {
+                    a.hide_row(i);
+                }
+                a.hide_col(j);
+            }
+            rows.push(r);
+            let stop = visit_in_place(a, rows, select_col, visit);
+            rows.pop();
+            a.undo(mark);
+            if stop { return true; }
+        }
+        false
+    }
+}
+
+/// Renderings of a matrix for people to read: an aligned text table,
+/// CSV, a LaTeX `array`, and an SVG picture.
+///
+/// Each takes a list of rows to highlight (say, the rows of a
+/// solution), which may be empty.
+pub mod render {
+    use std::fmt::{self, Write};
+    use super::{BitMatrix, ColLabelled, RowLabelled};
+
+    fn labels<L, F>(n: usize, label: F) -> Vec<String>
+        where L: fmt::Display, F: Fn(usize) -> L
+    {
+        (0..n).map(|i| label(i).to_string()).collect()
+    }
+
+    /// A table with the column labels across the top and the row
+    /// labels down the left, showing ones as `1` and zeros as `.`;
+    /// highlighted rows are marked with `*`.
+    pub fn text<L, M>(m: &M, highlight: &[usize]) -> String
+        where L: fmt::Display, M: BitMatrix+ColLabelled<L>+RowLabelled<L>
+    {
+        let cols = labels(m.num_cols(), |c| m.col_label(c));
+        let rows = labels(m.num_rows(), |r| m.row_label(r));
+        let marks = !highlight.is_empty();
+        let label_width = rows.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+        let mut out = String::new();
+        if marks { out.push_str("  "); }
+        out.push_str(&format!("{:w$}", "", w = label_width));
+        for c in cols.iter() {
+            out.push_str(&format!(" {}", c));
+        }
+        out.push('\n');
+        for (r, label) in rows.iter().enumerate() {
+            if marks {
+                out.push_str(if highlight.contains(&r) { "* " } else { "  " });
+            }
+            out.push_str(&format!("{:w$}", label, w = label_width));
+            for (c, col) in cols.iter().enumerate() {
+                let cell = if m.at(c, r) { "1" } else { "." };
+                out.push_str(&format!(" {:>w$}", cell, w = col.chars().count()));
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    fn csv_field(s: &str) -> String {
+        if s.contains([',', '"', '\n']) {
+            format!("\"{}\"", s.replace('"', "\"\""))
+        } else {
+            s.to_owned()
+        }
+    }
+
+    /// Comma-separated values: a header line of column labels, then
+    /// a line per row giving its label and its zeros and ones.  If
+    /// any rows are highlighted, a last column `selected` says which.
+    pub fn csv<L, M>(m: &M, highlight: &[usize]) -> String
+        where L: fmt::Display, M: BitMatrix+ColLabelled<L>+RowLabelled<L>
+    {
+        let marks = !highlight.is_empty();
+        let mut out = String::new();
+        out.push_str("row");
+        for c in 0..m.num_cols() {
+            out.push(',');
+            out.push_str(&csv_field(&m.col_label(c).to_string()));
+        }
+        if marks { out.push_str(",selected"); }
+        out.push('\n');
+        for r in 0..m.num_rows() {
+            out.push_str(&csv_field(&m.row_label(r).to_string()));
+            for c in 0..m.num_cols() {
+                out.push_str(if m.at(c, r) { ",1" } else { ",0" });
+            }
+            if marks {
+                out.push_str(if highlight.contains(&r) { ",1" } else { ",0" });
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    fn latex_escape(s: &str) -> String {
+        let mut out = String::new();
+        for ch in s.chars() {
+            match ch {
+                '_' | '&' | '%' | '$' | '#' | '{' | '}' => { out.push('\\'); out.push(ch); }
+                '\\' => out.push_str("\\backslash "),
+                '~' => out.push_str("\\sim "),
+                '^' => out.push_str("\\hat{}"),
+                _ => out.push(ch),
+            }
+        }
+        out
+    }
+
+    /// A LaTeX `array` (for math mode), with the row labels set off
+    /// by a rule; highlighted rows are set in bold.
+    pub fn latex<L, M>(m: &M, highlight: &[usize]) -> String
+        where L: fmt::Display, M: BitMatrix+ColLabelled<L>+RowLabelled<L>
+    {
+        let mut out = String::new();
+        writeln!(out, "\\begin{{array}}{{r|{}}}", "c".repeat(m.num_cols())).unwrap();
+        for c in 0..m.num_cols() {
+            write!(out, " & \\mathrm{{{}}}", latex_escape(&m.col_label(c).to_string())).unwrap();
+        }
+        out.push_str(" \\\\\n\\hline\n");
+        for r in 0..m.num_rows() {
+            let bold = highlight.contains(&r);
+            let font = if bold { "\\mathbf" } else { "\\mathrm" };
+            write!(out, "{}{{{}}}", font, latex_escape(&m.row_label(r).to_string())).unwrap();
+            for c in 0..m.num_cols() {
+                let cell = if m.at(c, r) { 1 } else { 0 };
+                if bold {
+                    write!(out, " & \\mathbf{{{}}}", cell).unwrap();
+                } else {
+                    write!(out, " & {}", cell).unwrap();
+                }
+            }
+            out.push_str(" \\\\\n");
+        }
+        out.push_str("\\end{array}\n");
+        out
+    }
+
+    fn xml_escape(s: &str) -> String {
+        s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+    }
+
+    /// An SVG picture with a dark square for each one, `cell` pixels
+    /// on a side, less a pixel of margin all round when `cell` is
+    /// over 2.  Highlighted rows get a tinted band, and their ones are
+    /// drawn in red.
+    pub fn svg<L, M>(m: &M, highlight: &[usize], cell: usize) -> String
+        where L: fmt::Display, M: BitMatrix+ColLabelled<L>+RowLabelled<L>
+    {
+        let cols = labels(m.num_cols(), |c| m.col_label(c));
+        let rows = labels(m.num_rows(), |r| m.row_label(r));
+        // Leave room for the labels, guessing at the width of a
+        // character as 0.6 of the font size (which is `cell` * 0.7).
+        let char_width = cell as f64 * 0.42;
+        let left = (rows.iter().map(|l| l.chars().count()).max().unwrap_or(0) as f64 * char_width)
+            .ceil() as usize + cell / 2;
+        let top = cell + cell / 2;
+        let width = left + cell * cols.len() + 1;
+        let height = top + cell * rows.len() + 1;
+        let font = cell * 7 / 10;
+        let margin = if cell > 2 { 1 } else { 0 };
+
+        let mut out = String::new();
+        writeln!(out, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+                       font-family=\"monospace\" font-size=\"{}\">", width, height, font).unwrap();
+        for (c, label) in cols.iter().enumerate() {
+            writeln!(out, "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{}</text>",
+                     left + c * cell + cell / 2, cell, xml_escape(label)).unwrap();
+        }
+        for (r, label) in rows.iter().enumerate() {
+            let y = top + r * cell;
+            let lit = highlight.contains(&r);
+            if lit {
+                writeln!(out, "<rect x=\"0\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"#ffe9a8\"/>",
+                         y, width, cell).unwrap();
+            }
+            writeln!(out, "<text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}</text>",
+                     left - cell / 4, y + cell * 3 / 4, xml_escape(label)).unwrap();
+            for c in 0..cols.len() {
+                if !m.at(c, r) { continue; }
+                writeln!(out, "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+                         left + c * cell + margin, y + margin,
+                         cell - 2 * margin, cell - 2 * margin,
+                         if lit { "#c0392b" } else { "#333333" }).unwrap();
+            }
+        }
+        writeln!(out, "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"#999999\"/>",
+                 left, top, cell * cols.len(), cell * rows.len()).unwrap();
+        out.push_str("</svg>\n");
+        out
+    }
+}
+
+/// Reading and writing problems in the input format of Knuth's DLX1
+/// program, so that instances can be exchanged with his C code.
+///
+/// The first line names the items, with primary items separated
+/// from any secondary ones by a lone `|`; each following line is an
+/// option, listing the items it covers.  Blank lines and lines that
+/// begin with `|` are comments, so there must be a primary item.
+/// For example:
+///
+/// ```text
+/// | a tiny problem
+/// a b c | x
+/// a b x
+/// c x
+/// c
+/// ```
+pub mod dlx1 {
+    use std::fs::File;
+    use std::io::{self, BufRead, BufReader, Write};
+    use std::path::Path;
+
+    use dancing_links::dlx_matrix;
+    use super::{BitMatrix, Matrix, Accumulate};
+
+    /// A parsed problem: the columns of `matrix` are the items, in
+    /// the order given, with the primary ones first; its rows are the
+    /// options, each labelled by its items separated by single spaces.
+    #[derive(Clone)]
+    pub struct Instance {
+        matrix: Matrix<String, usize>,
+        num_primary: usize,
+    }
+
+    impl Instance {
+        pub fn new(matrix: Matrix<String, usize>, num_primary: usize) -> Instance {
+            assert!(num_primary <= matrix.num_cols());
+            Instance { matrix, num_primary }
+        }
+
+        pub fn matrix(&self) -> &Matrix<String, usize> { &self.matrix }
+
+        pub fn num_primary(&self) -> usize { self.num_primary }
+
+        pub fn is_secondary(&self, col: usize) -> bool { col >= self.num_primary }
+
+        /// The instance as a `dlx_matrix`, with its secondary items
+        /// set up as secondary columns.
+        pub fn dlx(&self) -> dlx_matrix<String> {
+            let num_primary = self.num_primary;
+            dlx_matrix::with_secondary(&self.matrix, |c| c >= num_primary)
+        }
+
+        /// A matrix with only primary columns and the same solutions,
+        /// for use with `x::find_solutions` (which knows nothing of
+        /// secondary items).
+        ///
+        /// This is the usual reduction: each secondary item gets an
+        /// extra row covering just that item, labelled by the item's
+        /// name prefixed with `|` so that it cannot be mistaken for an
+        /// option.  Drop those rows from each solution found.
+        pub fn to_primary(&self) -> Matrix<String, usize> {
+            let m = &self.matrix;
+            let num_cols = m.num_cols();
+            let mut rows = m.rows.clone();
+            let mut elems = m.elems.clone();
+            for c in self.num_primary..num_cols {
+                rows.push(format!("|{}", m.cols[c]));
+                elems.extend((0..num_cols).map(|j| if j == c { 1 } else { 0 }));
+            }
+            Matrix::new(m.cols.clone(), rows, elems)
+        }
+
+        /// Fails, writing nothing, if there are no primary items, as
+        /// the item line would then be blank or a comment.
+        pub fn write_to<W:Write>(&self, w: &mut W) -> io::Result<()> {
+            if self.num_primary == 0 {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "no primary items"));
+            }
+            let m = &self.matrix;
+            for (c, item) in m.cols.iter().enumerate() {
+                if c == self.num_primary { write!(w, "| ")?; }
+                write!(w, "{}", item)?;
+                if c + 1 < m.cols.len() { write!(w, " ")?; }
+            }
+            writeln!(w)?;
+            for r in 0..m.num_rows() {
+                let cols: Vec<usize> = m.cols_on(r, &Accumulate);
+                let items: Vec<&str> = cols.iter().map(|&c| &m.cols[c][..]).collect();
+                writeln!(w, "{}", items.join(" "))?;
+            }
+            Ok(())
+        }
+
+        pub fn read_from<R:BufRead>(r: R) -> io::Result<Instance> {
+            fn bad(msg: String) -> io::Error {
+                io::Error::new(io::ErrorKind::InvalidData, msg)
+            }
+            let mut cols: Vec<String> = vec![];
+            let mut num_primary = None;
+            let mut rows = vec![];
+            let mut elems = vec![];
+            let mut seen_items = false;
+            for l in r.lines() {
+                let l = l?;
+                let l = l.trim();
+                if l.is_empty() || l.starts_with('|') { continue; }
+                if !seen_items {
+                    for word in l.split_whitespace() {
+                        if word == "|" {
+                            if num_primary.is_some() {
+                                return Err(bad(String::from("second `|` in item line")));
+                            }
+                            num_primary = Some(cols.len());
+                        } else if word.contains('|') || word.contains(':') {
+                            return Err(bad(format!("bad item name `{}`", word)));
+                        } else if cols.iter().any(|c| c == word) {
+                            return Err(bad(format!("duplicate item `{}`", word)));
+                        } else {
+                            cols.push(word.to_owned());
+                        }
+                    }
+                    if num_primary == Some(0) || cols.is_empty() {
+                        return Err(bad(String::from("no primary items")));
+                    }
+                    seen_items = true;
+                    continue;
+                }
+                let start = elems.len();
+                elems.extend(cols.iter().map(|_| 0));
+                for word in l.split_whitespace() {
+                    match cols.iter().position(|c| c == word) {
+                        None => return Err(bad(format!("unknown item `{}`", word))),
+                        Some(c) if elems[start + c] != 0 =>
+                            return Err(bad(format!("item `{}` repeated in option", word))),
+                        Some(c) => elems[start + c] = 1,
+                    }
+                }
+                rows.push(l.split_whitespace().collect::<Vec<_>>().join(" "));
+            }
+            if !seen_items {
+                return Err(bad(String::from("missing item line")));
+            }
+            let num_primary = num_primary.unwrap_or(cols.len());
+            Ok(Instance::new(Matrix::new(cols, rows, elems), num_primary))
+        }
+
+        pub fn save<P:AsRef<Path>>(&self, path: P) -> io::Result<()> {
+            let mut f = File::create(path)?;
+            self.write_to(&mut f)
+        }
+
+        pub fn load<P:AsRef<Path>>(path: P) -> io::Result<Instance> {
+            let f = File::open(path)?;
+            Instance::read_from(BufReader::new(f))
+        }
+    }
+
+    #[cfg(test)]
+    const EXAMPLE: &str = "\
+| Knuth's example (6) from TAOCP 7.2.2.1, with two extra items
+a b c d e f g | x y
+c e f
+a d g
+b c f x
+a d
+b g y
+d e g x
+";
+
+    #[test]
+    fn dlx1_read() {
+        let inst = Instance::read_from(EXAMPLE.as_bytes()).unwrap();
+        assert_eq!(inst.matrix().num_cols(), 9);
+        assert_eq!(inst.matrix().num_rows(), 6);
+        assert_eq!(inst.num_primary(), 7);
+        assert!(inst.is_secondary(7) && !inst.is_secondary(6));
+
+        use generators::n_tuples::Generator;
+        let mut m = inst.dlx();
+        let mut solns: Vec<Vec<String>> = vec![];
+        ::dancing_links::Dlx::new(&mut m).gen(|s| solns.push(s.into_owned()));
+        for s in &mut solns { s.sort(); }
+        assert_eq!(solns, [["a d", "b g y", "c e f"]]);
+
+        let padded = inst.to_primary();
+        let mut solns = super::x::find_solutions(
+            &padded, &vec![], &|m: &Matrix<_, _>| super::choose_nonzero_col(m).unwrap_or(0));
+        for s in &mut solns {
+            s.retain(|l: &String| !l.starts_with('|'));
+            s.sort();
+        }
+        assert_eq!(solns, [["a d", "b g y", "c e f"]]);
+    }
+
+    #[test]
+    fn dlx1_round_trip() {
+        let inst = Instance::read_from(EXAMPLE.as_bytes()).unwrap();
+        let mut out = vec![];
+        inst.write_to(&mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with("a b c d e f g | x y\nc e f\n"));
+        let again = Instance::read_from(text.as_bytes()).unwrap();
+        assert_eq!(again.num_primary(), inst.num_primary());
+        assert_eq!(again.matrix().rows, inst.matrix().rows);
+        assert_eq!(again.matrix().elems, inst.matrix().elems);
+    }
+
+    #[test]
+    fn dlx1_errors() {
+        assert!(Instance::read_from("| only a comment\n".as_bytes()).is_err());
+        assert!(Instance::read_from("a b\na c\n".as_bytes()).is_err());
+        assert!(Instance::read_from("a b\na a\n".as_bytes()).is_err());
+        assert!(Instance::read_from("| a b\n| x\n".as_bytes()).is_err());
+        assert!(Instance::read_from("a | b | c\n".as_bytes()).is_err());
+    }
+}
+
+/// Simplifying an instance before searching it, after the style of
+/// Knuth's DLX-PRE.
+///
+/// Each reduction keeps the solutions the same (once the rows that
+/// were forced are added back), and they are repeated until none of
+/// them applies:
+///
+/// * a primary column with a single row forces that row into every
+///   solution, so the row is taken out along with its columns and
+///   every row that clashes with it;
+/// * a row that covers no primary column is never chosen;
+/// * a secondary column in at most one row constrains nothing;
+/// * if every row with primary column `i` also has column `j`, rows
+///   with `j` but not `i` are useless and `j` is then redundant;
+/// * a row is blocked if some primary column it lacks can only be
+///   covered by rows that clash with it.
+pub mod pre {
+    use super::{BitMatrix, ColLabelled, RowLabelled, Matrix, Accumulate};
+
+    /// One step taken by `reduce`.  Rows and columns are numbered as
+    /// in the original instance.
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    pub enum Removal {
+        /// Primary `col` has no rows left, so there are no solutions;
+        /// reduction stops here.
+        Infeasible { col: usize },
+        /// `row` is the only row with primary `col`.
+        Forced { row: usize, col: usize },
+        /// `row` clashes with the forced row `forced`.
+        Clashes { row: usize, forced: usize },
+        /// `col` is covered by the forced row `by`.
+        Covered { col: usize, by: usize },
+        /// `row` covers no primary column.
+        Useless { row: usize },
+        /// Secondary `col` is in at most one row.
+        Unused { col: usize },
+        /// `row` has `col` but not `by`, though every row with `by`
+        /// has `col`.
+        Dominated { row: usize, col: usize, by: usize },
+        /// Every remaining row with `col` has `into`, and vice versa.
+        Merged { col: usize, into: usize },
+        /// Every row with primary `col` clashes with `row`.
+        Blocked { row: usize, col: usize },
+    }
+
+    /// A reduced instance, with the way back to the original one.
+    pub struct Reduced<L> {
+        matrix: Matrix<L, usize>,
+        secondary: Vec<bool>,
+        row_origin: Vec<usize>,
+        col_origin: Vec<usize>,
+        forced: Vec<L>,
+        removals: Vec<Removal>,
+        feasible: bool,
+    }
+
+    impl<L:Clone> Reduced<L> {
+        /// The reduced instance; its rows and columns keep their
+        /// original labels and relative order.
+        pub fn matrix(&self) -> &Matrix<L, usize> { &self.matrix }
+
+        pub fn is_secondary(&self, col: usize) -> bool { self.secondary[col] }
+
+        /// The original index of row `row` of the reduced matrix.
+        pub fn row_origin(&self, row: usize) -> usize { self.row_origin[row] }
+
+        /// The original index of column `col` of the reduced matrix.
+        pub fn col_origin(&self, col: usize) -> usize { self.col_origin[col] }
+
+        /// The labels of the rows that every solution contains.
+        pub fn forced(&self) -> &[L] { &self.forced }
+
+        pub fn removals(&self) -> &[Removal] { &self.removals }
+
+        /// False if reduction found that there are no solutions at all.
+        pub fn is_feasible(&self) -> bool { self.feasible }
+
+        /// Turns a solution of the reduced matrix into one of the
+        /// original, by adding the forced rows.
+        pub fn lift(&self, soln: &[L]) -> Vec<L> {
+            soln.iter().chain(self.forced.iter()).cloned().collect()
+        }
+    }
+
+    /// Reduces `input`, whose columns `c` with `is_secondary(c)` may
+    /// be covered at most once rather than exactly once.
+    pub fn reduce<L, M, P>(input: &M, is_secondary: P) -> Reduced<L>
+        where L: Clone, M: BitMatrix+ColLabelled<L>+RowLabelled<L>, P: Fn(usize) -> bool
+    {
+        let num_cols = input.num_cols();
+        let num_rows = input.num_rows();
+        let secondary: Vec<bool> = (0..num_cols).map(is_secondary).collect();
+        let mut s = State {
+            rows: (0..num_rows).map(|r| input.cols_on(r, &Accumulate)).collect(),
+            row_live: vec![true; num_rows],
+            col_live: vec![true; num_cols],
+            secondary,
+            forced: vec![],
+            removals: vec![],
+        };
+        let feasible = s.fixpoint();
+
+        let cols: Vec<usize> = (0..num_cols).filter(|&c| s.col_live[c]).collect();
+        let rows: Vec<usize> = (0..num_rows).filter(|&r| s.row_live[r]).collect();
+        let mut elems = vec![0; rows.len() * cols.len()];
+        for (i, &r) in rows.iter().enumerate() {
+            for (j, &c) in cols.iter().enumerate() {
+                if s.rows[r].contains(&c) { elems[i * cols.len() + j] = 1; }
+            }
+        }
+        Reduced {
+            matrix: Matrix::new(cols.iter().map(|&c| input.col_label(c).clone()).collect(),
+                                rows.iter().map(|&r| input.row_label(r).clone()).collect(),
+                                elems),
+            secondary: cols.iter().map(|&c| s.secondary[c]).collect(),
+            forced: s.forced.iter().map(|&r| input.row_label(r).clone()).collect(),
+            row_origin: rows,
+            col_origin: cols,
+            removals: s.removals,
+            feasible,
+        }
+    }
+
+    struct State {
+        rows: Vec<Vec<usize>>, // the live columns of each row
+        row_live: Vec<bool>,
+        col_live: Vec<bool>,
+        secondary: Vec<bool>,
+        forced: Vec<usize>,
+        removals: Vec<Removal>,
+    }
+
+    impl State {
+        fn rows_on(&self, col: usize) -> Vec<usize> {
+            (0..self.rows.len())
+                .filter(|&r| self.row_live[r] && self.rows[r].contains(&col))
+                .collect()
+        }
+
+        fn clash(&self, r1: usize, r2: usize) -> bool {
+            self.rows[r1].iter().any(|c| self.rows[r2].contains(c))
+        }
+
+        fn drop_row(&mut self, row: usize, why: Removal) {
+            self.row_live[row] = false;
+            self.removals.push(why);
+        }
+
+        fn drop_col(&mut self, col: usize, why: Removal) {
+            self.col_live[col] = false;
+            for r in self.rows.iter_mut() { r.retain(|&c| c != col); }
+            self.removals.push(why);
         }
 
-        return solns;
+        /// Applies reductions until none applies; false if the
+        /// instance turned out to have no solutions.
+        fn fixpoint(&mut self) -> bool {
+            let num_cols = self.col_live.len();
+            let num_rows = self.rows.len();
+            'progress: loop {
+                for c in (0..num_cols).filter(|&c| self.col_live[c]) {
+                    let on = self.rows_on(c);
+                    if self.secondary[c] {
+                        if on.len() <= 1 {
+                            self.drop_col(c, Removal::Unused { col: c });
+                            continue 'progress;
+                        }
+                    } else if on.is_empty() {
+                        self.removals.push(Removal::Infeasible { col: c });
+                        return false;
+                    } else if on.len() == 1 {
+                        let f = on[0];
+                        self.forced.push(f);
+                        self.drop_row(f, Removal::Forced { row: f, col: c });
+                        for r in 0..num_rows {
+                            if self.row_live[r] && self.clash(r, f) {
+                                self.drop_row(r, Removal::Clashes { row: r, forced: f });
+                            }
+                        }
+                        for c in self.rows[f].clone() {
+                            self.drop_col(c, Removal::Covered { col: c, by: f });
+                        }
+                        continue 'progress;
+                    }
+                }
+
+                for r in (0..num_rows).filter(|&r| self.row_live[r]) {
+                    if self.rows[r].iter().all(|&c| self.secondary[c]) {
+                        self.drop_row(r, Removal::Useless { row: r });
+                        continue 'progress;
+                    }
+                }
+
+                for i in (0..num_cols).filter(|&i| self.col_live[i] && !self.secondary[i]) {
+                    let on_i = self.rows_on(i);
+                    for j in (0..num_cols).filter(|&j| j != i && self.col_live[j]) {
+                        if !on_i.iter().all(|&r| self.rows[r].contains(&j)) { continue; }
+                        for r in self.rows_on(j) {
+                            if !self.rows[r].contains(&i) {
+                                self.drop_row(r, Removal::Dominated { row: r, col: j, by: i });
+                            }
+                        }
+                        self.drop_col(j, Removal::Merged { col: j, into: i });
+                        continue 'progress;
+                    }
+                }
+
+                for r in (0..num_rows).filter(|&r| self.row_live[r]) {
+                    for c in (0..num_cols).filter(|&c| self.col_live[c] && !self.secondary[c]) {
+                        if self.rows[r].contains(&c) { continue; }
+                        if self.rows_on(c).iter().all(|&r2| self.clash(r, r2)) {
+                            self.drop_row(r, Removal::Blocked { row: r, col: c });
+                            continue 'progress;
+                        }
+                    }
+                }
+
+                return true;
+            }
+        }
     }
 }
 
-fn choose_nonzero_col<M:BitMatrix>(m: &M) -> Option<uint> {
-    for c in range(0, m.num_cols()) {
-        for r in range(0, m.num_rows()) {
+pub fn choose_nonzero_col<M:BitMatrix>(m: &M) -> Option<usize> {
+    for c in 0..m.num_cols() {
+        for r in 0..m.num_rows() {
             if m.at(c, r) { return Some(c); }
         }
     }
-    return None;
+    None
 }
 
-pub fn simple_exact_cover_instance_1() -> Matrix<&'static str, uint> {
+pub fn simple_exact_cover_instance_1() -> Matrix<&'static str, usize> {
     Matrix {
         cols: vec!["a", "b", "c", "d", "e", "f", "g", ],
-        col_indent: " ".into_owned(),
         rows: vec!["1", "2", "3", "4", "5", "6", ],
         elems: vec![0, 0, 1, 0, 1, 1, 0,
                     1, 0, 0, 1, 0, 0, 1,
@@ -295,17 +1181,18 @@ pub fn simple_exact_cover_instance_1() -> Matrix<&'static str, uint> {
     }
 }
 
-fn solve_exact_cover_instance_1() {
+fn solve_exact_cover_instance_1() -> Vec<Vec<String>> {
     let m = simple_exact_cover_instance_1();
     let unconstrained_soln : Vec<String> = vec![];
     println!("simple_exact_cover_instance begin: {}", m);
     let solns = x::find_solutions(&m,
                                   &unconstrained_soln,
-                                  &|m| { choose_nonzero_col(m).unwrap_or(0) } );
+                                  &|m: &Matrix<_, _>| { choose_nonzero_col(m).unwrap_or(0) } );
     println!("simple_exact_cover_instance solns: {:?}", solns);
+    solns
 }
 
-pub fn simple_exact_cover_instance_2() -> Matrix<&'static str, uint> {
+pub fn simple_exact_cover_instance_2() -> Matrix<&'static str, usize> {
     // (This instance of the problem is not solvable; I was trying to debug
     //  why my transcription of Knuth's algorithm X goes wrong.  The bug ended
     //  up being that my defintiion of "A is empty" was based on the number of
@@ -314,25 +1201,206 @@ pub fn simple_exact_cover_instance_2() -> Matrix<&'static str, uint> {
     //  argue that this remains a deficiency in Knuth's presentation, but
     //  it is a pretty easy bug to fix once you see where it arises.)
     Matrix {
-        rows:      vec!["   a", "   b"],
-        col_indent: "    ".into_owned(),
+        rows: vec!["a", "b"],
         cols: vec![ "1", "2", "3"],
         elems: vec![0, 1, 1,
                     1, 1, 0, ]
     }
 }
 
-fn solve_exact_cover_instance_2() {
+fn solve_exact_cover_instance_2() -> Vec<Vec<String>> {
     let m = simple_exact_cover_instance_2();
     let unconstrained_soln : Vec<String> = vec![];
     println!("simple_exact_cover_instance begin: {}", m);
     let solns = x::find_solutions(&m,
                                   &unconstrained_soln,
-                                  &|m| { choose_nonzero_col(m).unwrap_or(0) } );
+                                  &|m: &Matrix<_, _>| { choose_nonzero_col(m).unwrap_or(0) } );
     println!("simple_exact_cover_instance solns: {:?}", solns);
+    solns
+}
+
+#[test]
+fn x_instance_1() {
+    let mut solns = solve_exact_cover_instance_1();
+    for s in &mut solns { s.sort(); }
+    assert_eq!(solns, [["1", "4", "5"]]);
+}
+
+#[test]
+fn x_instance_2() {
+    assert!(solve_exact_cover_instance_2().is_empty());
+}
+
+#[cfg(test)]
+fn sparse_copy<L:Clone>(m: &Matrix<L, usize>) -> SparseMatrix<L> {
+    let mut s = SparseMatrix::new(m.cols.clone());
+    for r in 0..m.num_rows() {
+        let ones: Vec<usize> = m.cols_on(r, &Accumulate);
+        s.push_row(m.rows[r].clone(), &ones);
+    }
+    s
+}
+
+#[test]
+fn sparse_matches_dense() {
+    let dense = simple_exact_cover_instance_1();
+    let sparse = sparse_copy(&dense);
+    assert_eq!(sparse.num_ones(), 16);
+    for (d, s) in [(dense.clone(), sparse.clone()),
+                       (dense.without_row(2), sparse.without_row(2)),
+                       (dense.without_col(3), sparse.without_col(3))] {
+        assert_eq!(d.num_rows(), s.num_rows());
+        assert_eq!(d.num_cols(), s.num_cols());
+        for r in 0..d.num_rows() {
+            assert_eq!(d.row(r), s.row(r));
+            let dc: Vec<usize> = d.cols_on(r, &Accumulate);
+            let sc: Vec<usize> = s.cols_on(r, &Accumulate);
+            assert_eq!(dc, sc);
+        }
+        for c in 0..d.num_cols() {
+            let dr: Vec<usize> = d.rows_on(c, &Accumulate);
+            let sr: Vec<usize> = s.rows_on(c, &Accumulate);
+            assert_eq!(dr, sr);
+        }
+    }
+
+    let unconstrained_soln : Vec<String> = vec![];
+    let mut solns = x::find_solutions(&sparse,
+                                      &unconstrained_soln,
+                                      &|m: &SparseMatrix<_>| { choose_nonzero_col(m).unwrap_or(0) } );
+    for s in &mut solns { s.sort(); }
+    assert_eq!(solns, [["1", "4", "5"]]);
+}
+
+#[test]
+fn x_in_place() {
+    let unconstrained_soln : Vec<String> = vec![];
+    let first_live = |a: &x::Active<Matrix<_, _>>| a.live_cols().iter().cloned().min().unwrap();
+    let m = simple_exact_cover_instance_1();
+    let mut solns = x::find_solutions_in_place(&m, &unconstrained_soln, &first_live);
+    for s in &mut solns { s.sort(); }
+    assert_eq!(solns, [["1", "4", "5"]]);
+    let m = simple_exact_cover_instance_2();
+    assert!(x::find_solutions_in_place(&m, &unconstrained_soln, &first_live).is_empty());
+
+    // Row i covers columns i and i+1 (mod 2n), so the only exact
+    // covers are the two perfect matchings of a long cycle.
+    let n = 1000;
+    let cols: Vec<String> = (0..2*n).map(|c| format!("c{}", c)).collect();
+    let mut m = SparseMatrix::new(cols);
+    for i in 0..2*n {
+        m.push_row(format!("r{}", i), &[i, (i + 1) % (2*n)]);
+    }
+    let solns = x::find_solutions_in_place(&m, &unconstrained_soln, &x::choose_min_rows);
+    assert_eq!(solns.len(), 2);
+    assert!(solns.iter().all(|s| s.len() == n));
 }
 
-fn main() {
-    simple_exact_cover_instance_1();
-    simple_exact_cover_instance_2();
+#[test]
+fn x_streaming() {
+    use generators::n_tuples::{Control, Generator};
+
+    // The exact covers of {0..5} by two-element rows are the 15
+    // perfect matchings of K_6.
+    let cols: Vec<String> = (0..6).map(|c| format!("{}", c)).collect();
+    let mut m = SparseMatrix::new(cols);
+    for i in 0..6 {
+        for j in i+1..6 {
+            m.push_row(format!("{}{}", i, j), &[i, j]);
+        }
+    }
+    let mut count = 0;
+    x::solutions(&m, x::choose_min_rows).gen(|s| {
+        assert_eq!(s.len(), 3);
+        count += 1;
+    });
+    assert_eq!(count, 15);
+
+    // Stopping early leaves the generator ready to start over.
+    let mut gen = x::solutions(&m, x::choose_min_rows);
+    let mut seen: Vec<Vec<String>> = vec![];
+    gen.gen(|s| {
+        seen.push(s.into_owned());
+        if seen.len() == 4 { Control::Break(()) } else { Control::Yield }
+    });
+    assert_eq!(seen.len(), 4);
+    let mut first = None;
+    gen.gen(|s| { first = Some(s.into_owned()); Control::Break(()) });
+    assert_eq!(first.as_ref(), Some(&seen[0]));
+}
+
+#[test]
+fn pre_instance_1() {
+    let m = simple_exact_cover_instance_1();
+    let r = pre::reduce(&m, |_| false);
+    assert!(r.is_feasible());
+    // Column a is in rows 2 and 4, both of which have d; so d merges
+    // into a, and row 6 (which has d but not a) goes.
+    assert!(r.removals().contains(&pre::Removal::Dominated { row: 5, col: 3, by: 0 }));
+    assert!(r.removals().contains(&pre::Removal::Merged { col: 3, into: 0 }));
+    // With row 6 gone, row 1 is the only one with e, so it is forced
+    // and takes c, e and f with it; every column that goes is
+    // accounted for.
+    assert!(r.removals().contains(&pre::Removal::Forced { row: 0, col: 4 }));
+    assert!(r.removals().contains(&pre::Removal::Covered { col: 2, by: 0 }));
+    for c in 0..m.cols.len() {
+        let kept = (0..r.matrix().cols.len()).any(|j| r.col_origin(j) == c);
+        let gone = r.removals().iter().any(|why| match *why {
+            pre::Removal::Covered { col, .. } |
+            pre::Removal::Merged { col, .. } |
+            pre::Removal::Unused { col } => col == c,
+            _ => false,
+        });
+        assert!(kept != gone, "column {}", m.cols[c]);
+    }
+
+    use generators::n_tuples::Generator;
+    let mut solns: Vec<Vec<&str>> = vec![];
+    x::solutions(r.matrix(), x::choose_min_rows).gen(|s| {
+        let mut s = r.lift(&s);
+        s.sort();
+        solns.push(s);
+    });
+    assert_eq!(solns, [["1", "4", "5"]]);
+    for (i, &l) in r.matrix().rows.iter().enumerate() {
+        assert_eq!(m.rows[r.row_origin(i)], l);
+    }
+
+    let m = simple_exact_cover_instance_2();
+    let r = pre::reduce(&m, |_| false);
+    assert!(!r.is_feasible());
+}
+
+#[test]
+fn render_formats() {
+    let m = simple_exact_cover_instance_1();
+    let soln = [0, 3, 4];
+    assert_eq!(render::text(&m, &soln),
+               concat!("    a b c d e f g\n",
+                       "* 1 . . 1 . 1 1 .\n",
+                       "  2 1 . . 1 . . 1\n",
+                       "  3 . 1 1 . . 1 .\n",
+                       "* 4 1 . . 1 . . .\n",
+                       "* 5 . 1 . . . . 1\n",
+                       "  6 . . . 1 1 . 1\n"));
+    assert_eq!(format!("{}", simple_exact_cover_instance_2()),
+               concat!("  1 2 3\n",
+                       "a . 1 1\n",
+                       "b 1 1 .\n"));
+    let csv = render::csv(&m, &soln);
+    assert!(csv.starts_with("row,a,b,c,d,e,f,g,selected\n1,0,0,1,0,1,1,0,1\n2,1,0,0,1,0,0,1,0\n"));
+    let latex = render::latex(&m, &soln);
+    assert!(latex.starts_with("\\begin{array}{r|ccccccc}\n & \\mathrm{a} & \\mathrm{b}"));
+    assert!(latex.contains("\\mathbf{1} & \\mathbf{0} & \\mathbf{0} & \\mathbf{1} & \\mathbf{0} & \\mathbf{1}"));
+    assert!(latex.contains("\n\\mathrm{2} & 1 & 0 & 0 & 1 & 0 & 0 & 1 \\\\\n"));
+    let svg = render::svg(&m, &soln, 20);
+    // One square per one, a band per highlighted row, and a frame.
+    assert_eq!(svg.matches("<rect").count(), 16 + 3 + 1);
+    assert_eq!(svg.matches("#c0392b").count(), 3 + 2 + 2);
+    // Cells too small for a margin are filled edge to edge.
+    for cell in 0..3 {
+        let svg = render::svg(&m, &soln, cell);
+        let square = format!("width=\"{}\" height=\"{}\" fill=\"#333333\"", cell, cell);
+        assert_eq!(svg.matches(&square[..]).count(), 16 - 7, "cell = {}", cell);
+    }
 }
@@ -0,0 +1,465 @@
+//! Classic puzzles, compiled into exact cover problems.
+//!
+//! Each builder emits a `dlx1::Instance` whose rows are labelled the
+//! way the DLX1 reader labels them: by the names of the items they
+//! cover, separated by spaces.  So an instance can be written out
+//! for Knuth's programs, read back in, and its solutions still
+//! decoded by the builder that made it.
+
+use std::collections::HashMap;
+
+use exact_cover::Matrix;
+use exact_cover::dlx1::Instance;
+
+/// Builds an instance from the names of its primary and secondary
+/// items and, for each option, the names of the items it covers.
+fn build(primary: Vec<String>, secondary: Vec<String>, options: Vec<Vec<String>>) -> Instance {
+    let num_primary = primary.len();
+    let mut cols = primary;
+    cols.extend(secondary);
+    let index: HashMap<&str, usize> =
+        cols.iter().enumerate().map(|(i, c)| (&c[..], i)).collect();
+    let mut rows = vec![];
+    let mut elems = vec![0; options.len() * cols.len()];
+    for (r, opt) in options.iter().enumerate() {
+        for item in opt.iter() {
+            elems[r * cols.len() + index[&item[..]]] = 1;
+        }
+        rows.push(opt.join(" "));
+    }
+    Instance::new(Matrix::new(cols.clone(), rows, elems), num_primary)
+}
+
+/// The number after `prefix` in `word`, e.g. 12 from "r12" with
+/// prefix "r".
+fn number_after(word: &str, prefix: &str) -> usize {
+    assert!(word.starts_with(prefix), "expected `{}` to start with `{}`", word, prefix);
+    word[prefix.len()..].parse().unwrap()
+}
+
+/// The word of `label` that starts with `prefix`; the words of a
+/// label read back from DLX1 come in the order of the items, not
+/// the order the builder gave them.
+fn word_starting<'a>(label: &'a str, prefix: &str) -> &'a str {
+    label.split_whitespace().find(|w| w.starts_with(prefix))
+        .unwrap_or_else(|| panic!("no word starting with `{}` in `{}`", prefix, label))
+}
+
+/// Placing `n` queens on an `n`-by-`n` board, none attacking another.
+///
+/// Rows `r` and columns `c` are primary items; the diagonals `a` (by
+/// sum) and `b` (by difference) are secondary, as at most one queen
+/// may sit on each.
+pub mod queens {
+    use exact_cover::dlx1::Instance;
+    use super::{build, number_after, word_starting};
+
+    pub struct Queens { n: usize }
+
+    impl Queens {
+        pub fn new(n: usize) -> Queens { Queens { n } }
+
+        pub fn instance(&self) -> Instance {
+            let n = self.n;
+            let primary = (0..n).map(|i| format!("r{}", i))
+                .chain((0..n).map(|j| format!("c{}", j)))
+                .collect();
+            let diagonals = (2*n).saturating_sub(1);
+            let secondary = (0..diagonals).map(|k| format!("a{}", k))
+                .chain((0..diagonals).map(|k| format!("b{}", k)))
+                .collect();
+            let mut options = vec![];
+            for i in 0..n {
+                for j in 0..n {
+                    options.push(vec![format!("r{}", i), format!("c{}", j),
+                                      format!("a{}", i + j), format!("b{}", n - 1 + i - j)]);
+                }
+            }
+            build(primary, secondary, options)
+        }
+
+        /// The column of the queen in each row.
+        pub fn decode<S:AsRef<str>>(&self, soln: &[S]) -> Vec<usize> {
+            let mut cols = vec![self.n; self.n];
+            for label in soln.iter() {
+                let label = label.as_ref();
+                let i = number_after(word_starting(label, "r"), "r");
+                let j = number_after(word_starting(label, "c"), "c");
+                cols[i] = j;
+            }
+            cols
+        }
+    }
+}
+
+/// Sudoku on an `n`-by-`n` grid with `b`-by-`b` boxes (`n == b*b`),
+/// digits running from 1 to `n`.
+///
+/// Item `p{r},{c}` says cell (r, c) is filled; `r{r},{d}`, `c{c},{d}`
+/// and `b{x},{d}` say digit d appears in row r, column c and box x.
+/// Cells given in advance get only the option for their digit.
+pub mod sudoku {
+    use exact_cover::dlx1::Instance;
+    use super::{build, number_after, word_starting};
+
+    pub struct Sudoku { b: usize, givens: Vec<Option<usize>> }
+
+    impl Sudoku {
+        /// A puzzle with `b`-by-`b` boxes and the given digits, in
+        /// row-major order (`None` for a blank).
+        pub fn new(b: usize, givens: Vec<Option<usize>>) -> Sudoku {
+            let n = b * b;
+            assert_eq!(givens.len(), n * n);
+            assert!(givens.iter().all(|g| g.is_none_or(|d| 1 <= d && d <= n)));
+            Sudoku { b, givens }
+        }
+
+        /// Reads the givens from `text`, skipping whitespace: a digit
+        /// from 1 to 9 (or a letter, for the digits from 10 up) is a
+        /// given, and `.` or `0` is a blank.
+        pub fn parse(b: usize, text: &str) -> Sudoku {
+            let givens = text.chars().filter(|c| !c.is_whitespace()).map(|c| {
+                match c {
+                    '.' | '0' => None,
+                    c => Some(c.to_digit(36).expect("bad sudoku digit") as usize),
+                }
+            }).collect();
+            Sudoku::new(b, givens)
+        }
+
+        pub fn instance(&self) -> Instance {
+            let (b, n) = (self.b, self.b * self.b);
+            let mut primary = vec![];
+            for x in 0..n {
+                for y in 0..n {
+                    primary.push(format!("p{},{}", x, y));
+                    primary.push(format!("r{},{}", x, y + 1));
+                    primary.push(format!("c{},{}", x, y + 1));
+                    primary.push(format!("b{},{}", x, y + 1));
+                }
+            }
+            let mut options = vec![];
+            for r in 0..n {
+                for c in 0..n {
+                    let x = (r / b) * b + c / b;
+                    for d in 1..n+1 {
+                        if self.givens[r * n + c].is_some_and(|g| g != d) { continue; }
+                        options.push(vec![format!("p{},{}", r, c), format!("r{},{}", r, d),
+                                          format!("c{},{}", c, d), format!("b{},{}", x, d)]);
+                    }
+                }
+            }
+            build(primary, vec![], options)
+        }
+
+        /// The filled grid, as rows of digits.
+        pub fn decode<S:AsRef<str>>(&self, soln: &[S]) -> Vec<Vec<usize>> {
+            let n = self.b * self.b;
+            let mut grid = vec![vec![0; n]; n];
+            for label in soln.iter() {
+                let label = label.as_ref();
+                let p = word_starting(label, "p");
+                let mut rc = p[1..].split(',').map(|s| s.parse::<usize>().unwrap());
+                let (r, c) = (rc.next().unwrap(), rc.next().unwrap());
+                let rd = word_starting(label, "r");
+                let d = number_after(&rd[rd.find(',').unwrap()..], ",");
+                grid[r][c] = d;
+            }
+            grid
+        }
+    }
+}
+
+/// Packing polyominoes into a board, each piece used exactly once
+/// and in any of its rotations and reflections.
+///
+/// Each piece is a primary item named by the piece; each cell of the
+/// board is an item `{row},{col}`, primary if every cell must be
+/// filled and secondary otherwise.
+pub mod polyominoes {
+    use exact_cover::dlx1::Instance;
+    use super::build;
+
+    pub type Cell = (usize, usize);
+
+    #[derive(Clone, PartialEq, Eq, Debug)]
+    pub struct Placement { pub piece: String, pub cells: Vec<Cell> }
+
+    pub struct Packing {
+        board: Vec<Cell>,
+        pieces: Vec<(String, Vec<Cell>)>,
+        fill: bool,
+    }
+
+    /// Parses a shape drawn with `#` for its cells (and anything
+    /// else, e.g. `.`, for gaps), one line per row.
+    pub fn shape(picture: &str) -> Vec<Cell> {
+        let mut cells = vec![];
+        for (r, line) in picture.lines().map(|l| l.trim()).filter(|l| !l.is_empty()).enumerate() {
+            for (c, ch) in line.chars().enumerate() {
+                if ch == '#' { cells.push((r, c)); }
+            }
+        }
+        cells
+    }
+
+    /// A `rows`-by-`cols` rectangle.
+    pub fn rectangle(rows: usize, cols: usize) -> Vec<Cell> {
+        (0..rows).flat_map(|r| (0..cols).map(move |c| (r, c))).collect()
+    }
+
+    /// The twelve pentominoes, with Conway's letters as their names.
+    pub fn pentominoes() -> Vec<(String, Vec<Cell>)> {
+        vec![("F", ".##\n##.\n.#."), ("I", "#####"), ("L", "####\n#..."),
+             ("N", "###.\n..##"), ("P", "###\n##."), ("T", "###\n.#.\n.#."),
+             ("U", "#.#\n###"), ("V", "#..\n#..\n###"), ("W", "#..\n##.\n.##"),
+             ("X", ".#.\n###\n.#."), ("Y", "####\n.#.."), ("Z", "##.\n.#.\n.##")]
+            .into_iter()
+            .map(|(name, picture)| (name.to_owned(), shape(picture)))
+            .collect()
+    }
+
+    /// The distinct rotations and reflections of `cells`, each moved
+    /// up against the top and left edges and sorted.
+    pub fn orientations(cells: &[Cell]) -> Vec<Vec<Cell>> {
+        let mut all: Vec<Vec<Cell>> = vec![];
+        let cells: Vec<(isize, isize)> = cells.iter().map(|&(r, c)| (r as isize, c as isize)).collect();
+        for k in 0..8 {
+            let moved: Vec<(isize, isize)> = cells.iter().map(|&(r, c)| {
+                let (r, c) = if k & 4 != 0 { (c, r) } else { (r, c) };
+                (if k & 1 != 0 { -r } else { r }, if k & 2 != 0 { -c } else { c })
+            }).collect();
+            let r0 = moved.iter().map(|p| p.0).min().unwrap();
+            let c0 = moved.iter().map(|p| p.1).min().unwrap();
+            let mut o: Vec<Cell> = moved.iter()
+                .map(|&(r, c)| ((r - r0) as usize, (c - c0) as usize))
+                .collect();
+            o.sort();
+            if !all.contains(&o) { all.push(o); }
+        }
+        all
+    }
+
+    fn cell_name(&(r, c): &Cell) -> String { format!("{},{}", r, c) }
+
+    impl Packing {
+        /// Packs `pieces` into `board`, which must be covered
+        /// completely if `fill` is true.
+        pub fn new(board: Vec<Cell>, pieces: Vec<(String, Vec<Cell>)>, fill: bool) -> Packing {
+            Packing { board, pieces, fill }
+        }
+
+        pub fn instance(&self) -> Instance {
+            let mut primary: Vec<String> = self.pieces.iter().map(|p| p.0.clone()).collect();
+            let cells: Vec<String> = self.board.iter().map(cell_name).collect();
+            let secondary = if self.fill {
+                primary.extend(cells);
+                vec![]
+            } else {
+                cells
+            };
+            let mut options = vec![];
+            for (name, piece) in self.pieces.iter() {
+                for o in orientations(piece) {
+                    // Anchor the orientation's first cell at each cell
+                    // of the board in turn.
+                    let (r0, c0) = o[0];
+                    for &(r, c) in self.board.iter() {
+                        if r < r0 { continue; }
+                        let placed: Option<Vec<Cell>> = o.iter().map(|&(dr, dc)| {
+                            if c + dc < c0 { return None; }
+                            let p = (r + dr - r0, c + dc - c0);
+                            if self.board.contains(&p) { Some(p) } else { None }
+                        }).collect();
+                        if let Some(placed) = placed {
+                            let mut opt = vec![name.clone()];
+                            opt.extend(placed.iter().map(cell_name));
+                            options.push(opt);
+                        }
+                    }
+                }
+            }
+            build(primary, secondary, options)
+        }
+
+        /// Where each piece went.
+        pub fn decode<S:AsRef<str>>(&self, soln: &[S]) -> Vec<Placement> {
+            soln.iter().map(|label| {
+                let mut words = label.as_ref().split_whitespace();
+                let piece = words.next().unwrap().to_owned();
+                let cells = words.map(|w| {
+                    let mut rc = w.split(',').map(|s| s.parse::<usize>().unwrap());
+                    (rc.next().unwrap(), rc.next().unwrap())
+                }).collect();
+                Placement { piece, cells }
+            }).collect()
+        }
+    }
+}
+
+/// Langford pairs: arranging two copies of each of 1, ..., n in a row
+/// so that the two k's have exactly k numbers between them.
+///
+/// Item `{k}` is the number k, and item `s{j}` is slot j.
+pub mod langford {
+    use exact_cover::dlx1::Instance;
+    use super::{build, number_after};
+
+    pub struct Langford { n: usize }
+
+    impl Langford {
+        pub fn new(n: usize) -> Langford { Langford { n } }
+
+        pub fn instance(&self) -> Instance {
+            let n = self.n;
+            let primary = (1..n+1).map(|k| format!("{}", k))
+                .chain((1..2*n+1).map(|j| format!("s{}", j)))
+                .collect();
+            let mut options = vec![];
+            for k in 1..n+1 {
+                for j in 1..2*n+1 {
+                    if j + k + 1 > 2*n { break; }
+                    options.push(vec![format!("{}", k), format!("s{}", j),
+                                      format!("s{}", j + k + 1)]);
+                }
+            }
+            build(primary, vec![], options)
+        }
+
+        /// The arrangement, as the number in each slot from left to
+        /// right.
+        pub fn decode<S:AsRef<str>>(&self, soln: &[S]) -> Vec<usize> {
+            let mut seq = vec![0; 2 * self.n];
+            for label in soln.iter() {
+                let mut words = label.as_ref().split_whitespace();
+                let k: usize = words.next().unwrap().parse().unwrap();
+                for w in words {
+                    seq[number_after(w, "s") - 1] = k;
+                }
+            }
+            seq
+        }
+    }
+}
+
+#[cfg(test)]
+fn all_solutions(inst: &Instance) -> Vec<Vec<String>> {
+    use dancing_links::Dlx;
+    use generators::n_tuples::Generator;
+    let mut m = inst.dlx();
+    let mut solns = vec![];
+    Dlx::new(&mut m).gen(|s| solns.push(s.into_owned()));
+    solns
+}
+
+#[test]
+fn puzzles_queens() {
+    let q = queens::Queens::new(8);
+    let solns = all_solutions(&q.instance());
+    assert_eq!(solns.len(), 92);
+    for s in solns.iter() {
+        let cols = q.decode(s);
+        for i in 0..8 {
+            for j in 0..i {
+                assert!(cols[i] != cols[j]);
+                assert!(cols[i] + j != cols[j] + i && cols[i] + i != cols[j] + j);
+            }
+        }
+    }
+}
+
+#[test]
+fn puzzles_sudoku() {
+    // Every 4-by-4 sudoku, from a blank grid.
+    let blank = sudoku::Sudoku::new(2, vec![None; 16]);
+    assert_eq!(all_solutions(&blank.instance()).len(), 288);
+
+    let puzzle = sudoku::Sudoku::parse(3, "
+        53..7....
+        6..195...
+        .98....6.
+        8...6...3
+        4..8.3..1
+        7...2...6
+        .6....28.
+        ...419..5
+        ....8..79");
+    let solns = all_solutions(&puzzle.instance());
+    assert_eq!(solns.len(), 1);
+    let grid = puzzle.decode(&solns[0]);
+    assert_eq!(grid[0], [5, 3, 4, 6, 7, 8, 9, 1, 2]);
+    assert_eq!(grid[8], [3, 4, 5, 2, 8, 6, 1, 7, 9]);
+}
+
+#[test]
+fn puzzles_pentominoes() {
+    use self::polyominoes::*;
+    assert_eq!(pentominoes().iter().map(|p| orientations(&p.1).len()).sum::<usize>(), 63);
+
+    // The 3-by-20 rectangle has two packings, up to symmetry.
+    let p = Packing::new(rectangle(3, 20), pentominoes(), true);
+    let solns = all_solutions(&p.instance());
+    assert_eq!(solns.len(), 2 * 4);
+    for s in solns.iter() {
+        let mut cells: Vec<Cell> = p.decode(s).into_iter().flat_map(|pl| pl.cells).collect();
+        cells.sort();
+        assert_eq!(cells, rectangle(3, 20));
+    }
+
+    // A board need not be filled when its cells are secondary.
+    let dominoes = vec![("a".to_owned(), shape("##")), ("b".to_owned(), shape("##"))];
+    let p = Packing::new(rectangle(1, 5), dominoes, false);
+    assert_eq!(all_solutions(&p.instance()).len(), 6);
+}
+
+#[test]
+fn puzzles_langford() {
+    let l = langford::Langford::new(3);
+    let solns = all_solutions(&l.instance());
+    // 231213 and its reversal.
+    let mut seqs: Vec<Vec<usize>> = solns.iter().map(|s| l.decode(s)).collect();
+    seqs.sort();
+    assert_eq!(seqs, [[2, 3, 1, 2, 1, 3], [3, 1, 2, 1, 3, 2]]);
+    assert_eq!(all_solutions(&langford::Langford::new(7).instance()).len(), 2 * 26);
+    assert!(all_solutions(&langford::Langford::new(5).instance()).is_empty());
+}
+
+#[test]
+fn puzzles_dlx1_round_trip() {
+    let q = queens::Queens::new(5);
+    let mut text = vec![];
+    q.instance().write_to(&mut text).unwrap();
+    let inst = Instance::read_from(&text[..]).unwrap();
+    let solns = all_solutions(&inst);
+    assert_eq!(solns.len(), 10);
+    assert_eq!(q.decode(&solns[0]).len(), 5);
+
+    // The labels come back with their words in item order, which for
+    // sudoku puts the `c` and `b` words before `p` and `r`.
+    let puzzle = sudoku::Sudoku::parse(2, "1...  ..2.  .3..  ...4");
+    let want = all_solutions(&puzzle.instance());
+    let mut text = vec![];
+    puzzle.instance().write_to(&mut text).unwrap();
+    let inst = Instance::read_from(&text[..]).unwrap();
+    let solns = all_solutions(&inst);
+    assert_eq!(solns.len(), want.len());
+    assert!(solns[0].iter().any(|l| !l.starts_with('p')));
+    assert_eq!(puzzle.decode(&solns[0]), puzzle.decode(&want[0]));
+
+    // The empty board has no items, which DLX1 cannot express, so it
+    // is refused on the way out rather than on the way back in.
+    let mut text = vec![];
+    assert!(queens::Queens::new(0).instance().write_to(&mut text).is_err());
+    assert!(text.is_empty());
+    let mut text = vec![];
+    queens::Queens::new(1).instance().write_to(&mut text).unwrap();
+    assert_eq!(all_solutions(&Instance::read_from(&text[..]).unwrap()), [["r0 c0 a0 b0"]]);
+}
+
+#[test]
+fn puzzles_queens_small() {
+    // No queens fit on the empty board in exactly one way.
+    assert_eq!(all_solutions(&queens::Queens::new(0).instance()), [Vec::<String>::new()]);
+    assert_eq!(all_solutions(&queens::Queens::new(1).instance()).len(), 1);
+    assert!(all_solutions(&queens::Queens::new(3).instance()).is_empty());
+}
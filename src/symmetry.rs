@@ -0,0 +1,440 @@
+//! Symmetry breaking for exact cover, given the symmetries.
+//!
+//! The user declares a group of automorphisms of the matrix, each a
+//! permutation of its rows together with one of its columns that
+//! carries every row onto a row with the permuted columns.  Such a
+//! permutation carries solutions to solutions, so the solutions fall
+//! into orbits, and we can report one solution per orbit: the
+//! canonical one, whose sorted row indices come first in
+//! lexicographic order among all its images.
+//!
+//! `Canonical` and `Orbits` wrap any solver that is a `Generator` of
+//! row labels (so `dancing_links::Dlx` and `exact_cover::x::solutions`
+//! alike), passing on only the canonical solutions; `Orbits` also
+//! says how big each orbit is.
+//!
+//! To keep the search from visiting every copy, `Group::prune` does
+//! what Knuth does for pentominoes when he restricts the placements
+//! of one piece: it picks a primary column and drops each row on it
+//! that some symmetry fixing the column takes to an earlier row.
+//! Every orbit still has a solution in the smaller matrix, and a
+//! solver over that matrix wrapped with `pruned` reports one member
+//! of each, the least of those the pruned search can reach.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use exact_cover::{BitMatrix, ColLabelled, RowLabelled, Accumulate, Matrix};
+use generators::n_tuples::{Control, Generator};
+
+/// A group of row permutations, given by generators.
+#[derive(Clone)]
+pub struct Group {
+    num_rows: usize,
+    gens: Vec<Vec<usize>>,
+}
+
+impl Group {
+    /// The trivial group on `num_rows` rows.
+    pub fn new(num_rows: usize) -> Group {
+        Group { num_rows, gens: vec![] }
+    }
+
+    /// Adds a generator that takes row `r` to row `rows[r]` and column
+    /// `c` to column `cols[c]`; panics unless this is an automorphism
+    /// of `m`.  (It is up to the caller to see that primary columns
+    /// go to primary columns, and secondary to secondary.)
+    pub fn add<M:BitMatrix>(&mut self, m: &M, rows: Vec<usize>, cols: Vec<usize>) {
+        assert_eq!(rows.len(), m.num_rows());
+        assert_eq!(cols.len(), m.num_cols());
+        assert!(is_permutation(&rows) && is_permutation(&cols), "not a permutation");
+        for (r, &to) in rows.iter().enumerate() {
+            let mut image: Vec<usize> = m.cols_on(r, &Accumulate).into_iter()
+                .map(|c| cols[c])
+                .collect();
+            image.sort();
+            let target: Vec<usize> = m.cols_on(to, &Accumulate);
+            assert!(image == target, "row {} does not map onto row {}", r, to);
+        }
+        self.add_rows(rows);
+    }
+
+    /// Adds the automorphism that takes column `c` to column
+    /// `cols[c]`, working out where each row goes; panics unless some
+    /// row has the image of each row's columns.  The rows of `m` must
+    /// differ in their columns.
+    pub fn add_cols<M:BitMatrix>(&mut self, m: &M, cols: Vec<usize>) {
+        assert_eq!(cols.len(), m.num_cols());
+        let by_cols: HashMap<Vec<usize>, usize> =
+            (0..m.num_rows()).map(|r| (m.cols_on(r, &Accumulate), r)).collect();
+        assert_eq!(by_cols.len(), m.num_rows(), "rows must differ in their columns");
+        let rows = (0..m.num_rows()).map(|r| {
+            let mut image: Vec<usize> = m.cols_on(r, &Accumulate).into_iter()
+                .map(|c| cols[c])
+                .collect();
+            image.sort();
+            *by_cols.get(&image).expect("no row onto which to map a row")
+        }).collect();
+        self.add(m, rows, cols);
+    }
+
+    /// Adds a generator given only by its action on rows, without
+    /// checking that it carries solutions to solutions.
+    pub fn add_rows(&mut self, rows: Vec<usize>) {
+        assert_eq!(rows.len(), self.num_rows);
+        assert!(is_permutation(&rows), "not a permutation");
+        self.gens.push(rows);
+    }
+
+    /// All the images of the set of rows `soln`, each sorted,
+    /// in increasing order.
+    pub fn orbit(&self, soln: &[usize]) -> Vec<Vec<usize>> {
+        let mut start = soln.to_vec();
+        start.sort();
+        let mut orbit = vec![start];
+        let mut i = 0;
+        while i < orbit.len() {
+            for g in self.gens.iter() {
+                let mut image: Vec<usize> = orbit[i].iter().map(|&r| g[r]).collect();
+                image.sort();
+                if !orbit.contains(&image) { orbit.push(image); }
+            }
+            i += 1;
+        }
+        orbit.sort();
+        orbit
+    }
+
+    /// The least image of `soln`.
+    pub fn canonical(&self, soln: &[usize]) -> Vec<usize> {
+        self.orbit(soln).swap_remove(0)
+    }
+
+    pub fn is_canonical(&self, soln: &[usize]) -> bool {
+        let mut sorted = soln.to_vec();
+        sorted.sort();
+        self.canonical(soln) == sorted
+    }
+
+    /// Every permutation in the group, the identity first.
+    pub fn elements(&self) -> Vec<Vec<usize>> {
+        let mut elements = vec![(0..self.num_rows).collect::<Vec<usize>>()];
+        let mut i = 0;
+        while i < elements.len() {
+            for g in self.gens.iter() {
+                let product: Vec<usize> = elements[i].iter().map(|&r| g[r]).collect();
+                if !elements.contains(&product) { elements.push(product); }
+            }
+            i += 1;
+        }
+        elements
+    }
+
+    /// Chooses the primary column of `m` (columns `c` with
+    /// `is_secondary(c)` are not) whose rows the group thins out the
+    /// most.  The symmetries that keep the rows on column `c` among
+    /// themselves split those rows into orbits, and only the least
+    /// row of each orbit need be tried: any solution can be carried
+    /// onto one that uses it.
+    pub fn prune<M, P>(&self, m: &M, is_secondary: P) -> Pruning
+        where M: BitMatrix, P: Fn(usize) -> bool
+    {
+        let elements = self.elements();
+        let mut best = Pruning { col: None, keep: vec![true; m.num_rows()] };
+        let mut most_dropped = 0;
+        for c in (0..m.num_cols()).filter(|&c| !is_secondary(c)) {
+            let on: Vec<bool> = (0..m.num_rows()).map(|r| m.at(c, r)).collect();
+            let fixing: Vec<&Vec<usize>> = elements.iter()
+                .filter(|g| (0..on.len()).all(|r| !on[r] || on[g[r]]))
+                .collect();
+            let keep: Vec<bool> = (0..on.len())
+                .map(|r| !on[r] || fixing.iter().all(|g| g[r] >= r))
+                .collect();
+            let dropped = keep.iter().filter(|&&k| !k).count();
+            if dropped > most_dropped || best.col.is_none() {
+                most_dropped = dropped;
+                best = Pruning { col: Some(c), keep };
+            }
+        }
+        best
+    }
+}
+
+/// The rows that `Group::prune` keeps.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Pruning {
+    col: Option<usize>,
+    keep: Vec<bool>,
+}
+
+impl Pruning {
+    /// The column whose rows were thinned out, if there was a primary
+    /// column at all.
+    pub fn col(&self) -> Option<usize> { self.col }
+
+    pub fn keeps(&self, row: usize) -> bool { self.keep[row] }
+
+    /// `m` with just the rows kept, which keep their labels.
+    pub fn matrix<L, M>(&self, m: &M) -> Matrix<L, usize>
+        where L: Clone, M: BitMatrix+ColLabelled<L>+RowLabelled<L>
+    {
+        let rows: Vec<usize> = (0..m.num_rows()).filter(|&r| self.keep[r]).collect();
+        let mut elems = Vec::with_capacity(rows.len() * m.num_cols());
+        for &r in rows.iter() {
+            elems.extend((0..m.num_cols()).map(|c| if m.at(c, r) { 1 } else { 0 }));
+        }
+        Matrix::new((0..m.num_cols()).map(|c| m.col_label(c).clone()).collect(),
+                    rows.iter().map(|&r| m.row_label(r).clone()).collect(),
+                    elems)
+    }
+
+    // The least image of a solution that a search over `matrix` can
+    // reach, from the sorted `orbit` of that solution.
+    fn least<'o>(&self, orbit: &'o [Vec<usize>]) -> &'o [usize] {
+        orbit.iter().find(|s| s.iter().all(|&r| self.keep[r])).expect("orbit out of reach")
+    }
+}
+
+fn is_permutation(p: &[usize]) -> bool {
+    let mut seen = vec![false; p.len()];
+    for &i in p.iter() {
+        if i >= p.len() || seen[i] { return false; }
+        seen[i] = true;
+    }
+    true
+}
+
+/// Maps the labels a solver reports back to row indices; this needs
+/// the row labels to be distinct.
+struct Rows<L> {
+    labels: Vec<L>,
+    index: HashMap<L, usize>,
+}
+
+impl<L:Clone+Hash+Eq> Rows<L> {
+    fn new<M:BitMatrix+RowLabelled<L>>(m: &M) -> Rows<L> {
+        let labels: Vec<L> = (0..m.num_rows()).map(|r| m.row_label(r).clone()).collect();
+        let index: HashMap<L, usize> =
+            labels.iter().cloned().enumerate().map(|(r, l)| (l, r)).collect();
+        assert_eq!(index.len(), labels.len(), "row labels must be distinct");
+        Rows { labels, index }
+    }
+
+    fn indices(&self, soln: &[L]) -> Vec<usize> {
+        soln.iter().map(|l| self.index[l]).collect()
+    }
+}
+
+/// The canonical solutions reported by `solver`, which must solve the
+/// matrix given to `new`.
+pub struct Canonical<'g, G, L> {
+    solver: G,
+    group: &'g Group,
+    pruning: Option<&'g Pruning>,
+    rows: Rows<L>,
+}
+
+impl<'g, G, L:Clone+Hash+Eq> Canonical<'g, G, L> {
+    pub fn new<M:BitMatrix+RowLabelled<L>>(solver: G, group: &'g Group, m: &M) -> Canonical<'g, G, L> {
+        Canonical { solver, group, pruning: None, rows: Rows::new(m) }
+    }
+
+    /// For a `solver` of `pruning.matrix(m)` instead: a solution is
+    /// then canonical if it is the least image the solver can reach.
+    pub fn pruned(self, pruning: &'g Pruning) -> Canonical<'g, G, L> {
+        Canonical { pruning: Some(pruning), ..self }
+    }
+}
+
+impl<'g, G, L> Generator for Canonical<'g, G, L>
+    where G: Generator<Item=[L], Final=()>, L: Clone+Hash+Eq
+{
+    type Item = [L];
+    type Final = ();
+    fn gen<F, R>(&mut self, mut visit: F)
+        where F: for <'a> FnMut(Cow<'a, [L]>) -> R,
+              R: Into<Control<()>>
+    {
+        let group = self.group;
+        let pruning = self.pruning;
+        let rows = &self.rows;
+        self.solver.gen(|soln: Cow<[L]>| {
+            let mut indices = rows.indices(&soln);
+            indices.sort();
+            let orbit = group.orbit(&indices);
+            let least = pruning.map_or(&orbit[0][..], |p| p.least(&orbit));
+            if least == &indices[..] {
+                visit(soln).into()
+            } else {
+                Control::Yield
+            }
+        })
+    }
+}
+
+/// One orbit of solutions: its canonical member, as row labels in
+/// increasing order of row, and how many solutions it holds.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Orbit<L> {
+    pub rep: Vec<L>,
+    pub size: usize,
+}
+
+/// Like `Canonical`, but reporting each orbit with its size.
+pub struct Orbits<'g, G, L> {
+    solver: G,
+    group: &'g Group,
+    pruning: Option<&'g Pruning>,
+    rows: Rows<L>,
+}
+
+impl<'g, G, L:Clone+Hash+Eq> Orbits<'g, G, L> {
+    pub fn new<M:BitMatrix+RowLabelled<L>>(solver: G, group: &'g Group, m: &M) -> Orbits<'g, G, L> {
+        Orbits { solver, group, pruning: None, rows: Rows::new(m) }
+    }
+
+    /// As for `Canonical::pruned`; the sizes are still those of the
+    /// whole orbits.
+    pub fn pruned(self, pruning: &'g Pruning) -> Orbits<'g, G, L> {
+        Orbits { pruning: Some(pruning), ..self }
+    }
+}
+
+impl<'g, G, L> Generator for Orbits<'g, G, L>
+    where G: Generator<Item=[L], Final=()>, L: Clone+Hash+Eq
+{
+    type Item = Orbit<L>;
+    type Final = ();
+    fn gen<F, R>(&mut self, mut visit: F)
+        where F: for <'a> FnMut(Cow<'a, Orbit<L>>) -> R,
+              R: Into<Control<()>>
+    {
+        let group = self.group;
+        let pruning = self.pruning;
+        let rows = &self.rows;
+        self.solver.gen(|soln: Cow<[L]>| {
+            let mut soln = rows.indices(&soln);
+            soln.sort();
+            let orbit = group.orbit(&soln);
+            let least = pruning.map_or(&orbit[0][..], |p| p.least(&orbit));
+            if least != &soln[..] { return Control::Yield; }
+            let rep = soln.iter().map(|&r| rows.labels[r].clone()).collect();
+            visit(Cow::Owned(Orbit { rep, size: orbit.len() })).into()
+        })
+    }
+}
+
+#[cfg(test)]
+fn queens_symmetries<M:BitMatrix>(n: usize, m: &M) -> Group {
+    // The columns are r0..r(n-1), c0..c(n-1), then the sum diagonals
+    // a0..a(2n-2) and the difference diagonals b0..b(2n-2), where
+    // square (i, j) is on a(i+j) and b(n-1+i-j).  Any padding rows
+    // for secondary columns come last, one for each diagonal.
+    let col = |family: usize, k: usize| match family {
+        0 => k, 1 => n + k, 2 => 2*n + k, _ => 2*n + 2*n-1 + k,
+    };
+    let mut group = Group::new(m.num_rows());
+
+    // A quarter turn takes (i, j) to (j, n-1-i).
+    let mut cols = vec![0; m.num_cols()];
+    for k in 0..n { cols[col(0, k)] = col(1, n-1-k); cols[col(1, k)] = col(0, k); }
+    for k in 0..2*n-1 { cols[col(2, k)] = col(3, k); cols[col(3, k)] = col(2, 2*n-2-k); }
+    group.add_cols(m, cols);
+
+    // A mirror takes (i, j) to (i, n-1-j).
+    let mut cols = vec![0; m.num_cols()];
+    for k in 0..n { cols[col(0, k)] = col(0, k); cols[col(1, k)] = col(1, n-1-k); }
+    for k in 0..2*n-1 { cols[col(2, k)] = col(3, k); cols[col(3, k)] = col(2, k); }
+    group.add_cols(m, cols);
+    group
+}
+
+#[test]
+fn symmetry_queens() {
+    use dancing_links::{dlx_matrix, Dlx};
+    use exact_cover::x;
+    use puzzles::queens::Queens;
+
+    // The 92 solutions of the eight queens problem fall into 12
+    // orbits under the symmetries of the square: 11 of size 8 and
+    // one of size 4.
+    let want = [4, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8, 8];
+    let inst = Queens::new(8).instance();
+    let group = queens_symmetries(8, inst.matrix());
+
+    let mut m = inst.dlx();
+    let mut sizes = vec![];
+    Orbits::new(Dlx::new(&mut m), &group, inst.matrix()).gen(|o| sizes.push(o.size));
+    sizes.sort();
+    assert_eq!(sizes, want);
+
+    let mut count = 0;
+    Canonical::new(Dlx::new(&mut m), &group, inst.matrix()).gen(|_| count += 1);
+    assert_eq!(count, 12);
+
+    // Only the mirror keeps the first rank of the board to itself, so
+    // pruning leaves a queen on it in one of the first four files, and
+    // the search finds half as many solutions.
+    let pruning = group.prune(inst.matrix(), |c| inst.is_secondary(c));
+    assert_eq!(pruning.col(), Some(0));
+    let pruned = pruning.matrix(inst.matrix());
+    let num_primary = inst.num_primary();
+    let mut pm = dlx_matrix::with_secondary(&pruned, |c| c >= num_primary);
+    let mut count = 0;
+    Dlx::new(&mut pm).gen(|_| count += 1);
+    assert_eq!(count, 46);
+    let mut sizes = vec![];
+    Orbits::new(Dlx::new(&mut pm), &group, inst.matrix()).pruned(&pruning)
+        .gen(|o| sizes.push(o.size));
+    sizes.sort();
+    assert_eq!(sizes, want);
+    let mut count = 0;
+    Canonical::new(Dlx::new(&mut pm), &group, inst.matrix()).pruned(&pruning)
+        .gen(|_| count += 1);
+    assert_eq!(count, 12);
+
+    // Algorithm X knows nothing of secondary columns, so give it the
+    // padded matrix, whose extra rows go wherever their diagonals go.
+    let padded = inst.to_primary();
+    let group = queens_symmetries(8, &padded);
+    let mut sizes = vec![];
+    let solver = x::solutions(&padded, x::choose_min_rows);
+    Orbits::new(solver, &group, &padded).gen(|o| sizes.push(o.size));
+    sizes.sort();
+    assert_eq!(sizes, want);
+}
+
+#[test]
+fn symmetry_langford() {
+    use exact_cover::x;
+    use puzzles::langford::Langford;
+
+    // Reversing the slots takes the one Langford arrangement of order
+    // 4 to the other; the columns are 1..n, then slots s1..s(2n).
+    let n = 4;
+    let inst = Langford::new(n).instance();
+    let m = inst.matrix();
+    let mut group = Group::new(m.num_rows());
+    group.add_cols(m, (0..m.num_cols()).map(|c| if c < n { c } else { 4*n - 1 - c }).collect());
+
+    let mut orbits = vec![];
+    Orbits::new(x::solutions(m, x::choose_min_rows), &group, m).gen(|o| orbits.push(o.into_owned()));
+    assert_eq!(orbits.len(), 1);
+    assert_eq!(orbits[0].size, 2);
+    assert_eq!(orbits[0].rep.len(), n);
+
+    // With the pruned matrix only one of the pair is found at all.
+    assert_eq!(group.elements().len(), 2);
+    let pruning = group.prune(m, |_| false);
+    let pruned = pruning.matrix(m);
+    assert!(pruned.num_rows() < m.num_rows());
+    let mut found = vec![];
+    x::solutions(&pruned, x::choose_min_rows).gen(|s| found.push(s.into_owned()));
+    assert_eq!(found.len(), 1);
+    let mut pruned_orbits = vec![];
+    Orbits::new(x::solutions(&pruned, x::choose_min_rows), &group, m).pruned(&pruning)
+        .gen(|o| pruned_orbits.push(o.into_owned()));
+    assert_eq!(pruned_orbits.len(), 1);
+    assert_eq!(pruned_orbits[0].size, 2);
+}
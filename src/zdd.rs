@@ -0,0 +1,193 @@
+//! Zero-suppressed decision diagrams, for families of sets of small
+//! integers (in our case, the sets of rows making up exact covers).
+//!
+//! A node is either one of the two sinks, `BOTTOM` (the empty family)
+//! and `TOP` (the family holding just the empty set), or a branch on
+//! a variable `v`, standing for the sets of its `lo` child together
+//! with the sets of its `hi` child each with `v` added.  Along any
+//! path no variable appears twice, and the `lo` and `hi` families of
+//! a branch never share a set; but, as with the diagrams that Knuth's
+//! DXZ builds, variables need not appear in the same order on every
+//! path.  Counting, sampling and visiting the sets only need the two
+//! properties above.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use generators::n_tuples::{Control, Generator};
+
+pub type Node = usize;
+
+pub const BOTTOM: Node = 0;
+pub const TOP: Node = 1;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+struct Branch { var: usize, lo: Node, hi: Node }
+
+/// Makes the nodes of a `Zdd`, sharing identical ones.  Children must
+/// be made before their parents.
+pub struct Builder {
+    nodes: Vec<Branch>,
+    unique: HashMap<Branch, Node>,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Builder {
+    pub fn new() -> Builder {
+        // Placeholders for the sinks, so that node ids index `nodes`.
+        let sink = Branch { var: usize::MAX, lo: BOTTOM, hi: BOTTOM };
+        Builder { nodes: vec![sink, sink], unique: HashMap::new() }
+    }
+
+    /// The node for `lo` together with `hi` (each set with `var`
+    /// added); as usual, a branch whose `hi` is `BOTTOM` is just `lo`.
+    pub fn node(&mut self, var: usize, lo: Node, hi: Node) -> Node {
+        assert!(lo < self.nodes.len() && hi < self.nodes.len());
+        if hi == BOTTOM { return lo; }
+        let b = Branch { var, lo, hi };
+        if let Some(&n) = self.unique.get(&b) { return n; }
+        let n = self.nodes.len();
+        self.nodes.push(b);
+        self.unique.insert(b, n);
+        n
+    }
+
+    pub fn finish(self, root: Node) -> Zdd {
+        let counts = counts(&self.nodes);
+        Zdd { nodes: self.nodes, counts, root }
+    }
+}
+
+/// The number of sets below each node; children come before parents.
+fn counts(nodes: &[Branch]) -> Vec<u128> {
+    let mut counts = vec![0, 1];
+    for b in nodes[2..].iter() {
+        let c = counts[b.lo] + counts[b.hi];
+        counts.push(c);
+    }
+    counts
+}
+
+pub struct Zdd {
+    nodes: Vec<Branch>,
+    counts: Vec<u128>,
+    root: Node,
+}
+
+impl Zdd {
+    pub fn root(&self) -> Node { self.root }
+
+    /// The number of branch nodes (not counting the sinks).
+    pub fn size(&self) -> usize { self.nodes.len() - 2 }
+
+    pub fn var(&self, n: Node) -> usize { assert!(n > TOP); self.nodes[n].var }
+    pub fn lo(&self, n: Node) -> Node { assert!(n > TOP); self.nodes[n].lo }
+    pub fn hi(&self, n: Node) -> Node { assert!(n > TOP); self.nodes[n].hi }
+
+    /// The number of sets in the family.
+    pub fn count(&self) -> u128 { self.counts[self.root] }
+
+    /// The number of sets in the family that contain `var`.
+    pub fn count_containing(&self, var: usize) -> u128 {
+        // paths[n] is the number of ways down from the root to n;
+        // parents have larger ids than their children.
+        let mut paths = vec![0u128; self.nodes.len()];
+        paths[self.root] = 1;
+        let mut total = 0;
+        for n in (TOP+1..self.root+1).rev() {
+            if paths[n] == 0 { continue; }
+            let b = self.nodes[n];
+            paths[b.lo] += paths[n];
+            paths[b.hi] += paths[n];
+            if b.var == var { total += paths[n] * self.counts[b.hi]; }
+        }
+        total
+    }
+
+    /// A set chosen uniformly at random from the family, or `None`
+    /// if the family is empty; `below(k)` must return a uniformly
+    /// random number less than `k`.
+    pub fn sample<R:FnMut(u128) -> u128>(&self, mut below: R) -> Option<Vec<usize>> {
+        if self.count() == 0 { return None; }
+        let mut set = vec![];
+        let mut n = self.root;
+        while n != TOP {
+            let b = self.nodes[n];
+            if below(self.counts[n]) < self.counts[b.hi] {
+                set.push(b.var);
+                n = b.hi;
+            } else {
+                n = b.lo;
+            }
+        }
+        Some(set)
+    }
+}
+
+/// Visits every set in the family, as its variables in the order met
+/// on the way down.
+impl Generator for Zdd {
+    type Item = [usize];
+    type Final = ();
+    fn gen<F, R>(&mut self, mut visit: F)
+        where F: for <'a> FnMut(Cow<'a, [usize]>) -> R,
+              R: Into<Control<()>>
+    {
+        // Each entry is a node still to explore and the length of
+        // `set` when it was pushed.
+        let mut set = vec![];
+        let mut todo = vec![(self.root, 0)];
+        while let Some((n, len)) = todo.pop() {
+            set.truncate(len);
+            if n == BOTTOM { continue; }
+            if n == TOP {
+                match visit(Cow::Borrowed(&set[..])).into() {
+                    Control::Break(()) => return,
+                    Control::Yield => continue,
+                }
+            }
+            let b = self.nodes[n];
+            todo.push((b.lo, len));
+            set.push(b.var);
+            todo.push((b.hi, len + 1));
+        }
+    }
+}
+
+#[test]
+fn zdd_basics() {
+    // The family {{0, 1}, {0, 2}, {2}}.
+    let mut b = Builder::new();
+    let one = b.node(1, BOTTOM, TOP);
+    let two = b.node(2, BOTTOM, TOP);
+    let one_or_two = b.node(1, two, TOP);
+    assert_eq!(b.node(1, two, TOP), one_or_two);
+    assert_eq!(b.node(5, one, BOTTOM), one);
+    let root = b.node(0, two, one_or_two);
+    let mut z = b.finish(root);
+    assert_eq!(z.size(), 4);
+    assert_eq!(z.count(), 3);
+    assert_eq!((z.count_containing(0), z.count_containing(1), z.count_containing(2)), (2, 1, 2));
+
+    let mut sets: Vec<Vec<usize>> = vec![];
+    z.gen(|s| { let mut s = s.into_owned(); s.sort(); sets.push(s); });
+    sets.sort();
+    assert_eq!(sets, [vec![0, 1], vec![0, 2], vec![2]]);
+
+    // Cycling `below` through 1, 2, ..., 6, 0 reaches each set about
+    // equally often (exactly so, were its values uniform).
+    let mut k = 0;
+    let mut seen: HashMap<Vec<usize>, usize> = HashMap::new();
+    for _ in 0..600 {
+        let s = z.sample(|n| { k = (k + 1) % 7; k as u128 % n }).unwrap();
+        *seen.entry(s).or_insert(0) += 1;
+    }
+    assert_eq!((seen[&vec![0, 1]], seen[&vec![0, 2]], seen[&vec![2]]), (201, 199, 200));
+
+    assert_eq!(Builder::new().finish(BOTTOM).sample(|_| 0), None);
+}